@@ -10,8 +10,25 @@ pub fn insert_pop(c: &mut Criterion) {
     c.bench_function("insert_pop", |b| {
         b.iter(|| {
             let mut map = PriorityMap::new();
-            for entry_id in 0..num_entries {
-                map.insert(priorities[entry_id], entry_id as u128, [0; 512]);
+            for (entry_id, priority) in priorities.iter().enumerate() {
+                map.insert(*priority, entry_id as u128, [0; 512]);
+            }
+            for _ in 0..num_entries {
+                map.pop();
+            }
+        })
+    });
+}
+
+pub fn insert_pop_4ary(c: &mut Criterion) {
+    let num_entries = 10_000;
+    let priorities: Vec<_> = (0..num_entries).map(|_| rand::random::<u64>()).collect();
+
+    c.bench_function("insert_pop_4ary", |b| {
+        b.iter(|| {
+            let mut map = PriorityMap::new().into_arity::<4>();
+            for (entry_id, priority) in priorities.iter().enumerate() {
+                map.insert(*priority, entry_id as u128, [0; 512]);
             }
             for _ in 0..num_entries {
                 map.pop();
@@ -20,12 +37,30 @@ pub fn insert_pop(c: &mut Criterion) {
     });
 }
 
+pub fn from_iter_pop(c: &mut Criterion) {
+    let num_entries = 10_000;
+    let priorities: Vec<_> = (0..num_entries).map(|_| rand::random::<u64>()).collect();
+
+    c.bench_function("from_iter_pop", |b| {
+        b.iter(|| {
+            let mut map: PriorityMap<_, _, _> = priorities
+                .iter()
+                .enumerate()
+                .map(|(entry_id, priority)| (*priority, entry_id as u128, [0; 512]))
+                .collect();
+            for _ in 0..num_entries {
+                map.pop();
+            }
+        })
+    });
+}
+
 pub fn reprioritize(c: &mut Criterion) {
     let num_entries = 10_000;
     let priorities: Vec<_> = (0..num_entries).map(|_| rand::random::<u64>()).collect();
     let mut map = PriorityMap::new();
-    for entry_id in 0..num_entries {
-        map.insert(priorities[entry_id], entry_id as u128, [0; 512]);
+    for (entry_id, priority) in priorities.iter().enumerate() {
+        map.insert(*priority, entry_id as u128, [0; 512]);
     }
 
     c.bench_function("reprioritize", |b| {
@@ -39,6 +74,7 @@ pub fn reprioritize(c: &mut Criterion) {
 
 struct Value {
     priority: u64,
+    #[allow(dead_code)]
     value: [u8; 512],
 }
 
@@ -50,7 +86,7 @@ impl PartialEq for Value {
 
 impl PartialOrd for Value {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.priority.partial_cmp(&other.priority)
+        Some(self.cmp(other))
     }
 }
 
@@ -69,11 +105,11 @@ pub fn insert_pop_pq(c: &mut Criterion) {
     c.bench_function("insert_pop_pq", |b| {
         b.iter(|| {
             let mut map = PriorityQueue::new();
-            for entry_id in 0..num_entries {
+            for (entry_id, priority) in priorities.iter().enumerate() {
                 map.push(
                     entry_id as u128,
                     Value {
-                        priority: priorities[entry_id],
+                        priority: *priority,
                         value: [0; 512],
                     },
                 );
@@ -89,11 +125,11 @@ pub fn reprioritize_pq(c: &mut Criterion) {
     let num_entries = 10_000;
     let priorities: Vec<_> = (0..num_entries).map(|_| rand::random::<u64>()).collect();
     let mut map = PriorityQueue::new();
-    for entry_id in 0..num_entries {
+    for (entry_id, priority) in priorities.iter().enumerate() {
         map.push(
             entry_id as u128,
             Value {
-                priority: priorities[entry_id],
+                priority: *priority,
                 value: [0; 512],
             },
         );
@@ -113,6 +149,8 @@ pub fn reprioritize_pq(c: &mut Criterion) {
 criterion_group!(
     benches,
     insert_pop,
+    insert_pop_4ary,
+    from_iter_pop,
     insert_pop_pq,
     reprioritize,
     reprioritize_pq
@@ -0,0 +1,212 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::time::{Duration, Instant};
+
+use crate::order::{Max, PriorityOrder};
+use crate::PriorityMap;
+
+/// Computes the priority of an entry that has waited for some time.
+///
+/// Implemented for any `Fn(&P, Duration) -> P` closure.
+pub trait AgingPolicy<P> {
+    /// Returns the priority of an entry inserted with priority `base` that has waited for
+    /// `waited`.
+    fn age(&self, base: &P, waited: Duration) -> P;
+}
+
+impl<P, F> AgingPolicy<P> for F
+where
+    F: Fn(&P, Duration) -> P,
+{
+    fn age(&self, base: &P, waited: Duration) -> P {
+        self(base, waited)
+    }
+}
+
+/// A [`PriorityMap`] whose entries gain priority while they wait, so that low priorities
+/// do not starve.
+///
+/// Every entry keeps the priority it was inserted with as its base. [`tick`](Self::tick)
+/// recomputes all priorities from their base and waiting time with the [`AgingPolicy`]
+/// `A`, and restores the heap with a single `O(n)` rebuild. Between ticks, priorities do
+/// not change.
+pub struct AgingPriorityMap<P, K, V, A, S = RandomState, O = Max>
+where
+    K: Hash,
+{
+    map: PriorityMap<P, K, Aged<P, V>, S, O>,
+    policy: A,
+}
+
+struct Aged<P, V> {
+    base: P,
+    since: Instant,
+    value: V,
+}
+
+impl<P, K, V, A> AgingPriorityMap<P, K, V, A>
+where
+    P: PartialOrd,
+    K: Eq + Hash,
+    A: AgingPolicy<P>,
+{
+    pub fn new(policy: A) -> Self {
+        Self::with_order(policy, Max)
+    }
+}
+
+impl<P, K, V, A, O> AgingPriorityMap<P, K, V, A, RandomState, O>
+where
+    K: Eq + Hash,
+    A: AgingPolicy<P>,
+    O: PriorityOrder<P>,
+{
+    pub fn with_order(policy: A, order: O) -> Self {
+        Self {
+            map: PriorityMap::with_order(order),
+            policy,
+        }
+    }
+}
+
+impl<P, K, V, A, S, O> AgingPriorityMap<P, K, V, A, S, O>
+where
+    K: Eq + Hash,
+    A: AgingPolicy<P>,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+{
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Inserts an entry that starts waiting now, replacing the base priority and value of
+    /// an existing entry with the same key.
+    ///
+    /// Returns the previous base priority and value.
+    pub fn insert(&mut self, priority: P, key: K, value: V) -> Option<(P, V)> {
+        let now = Instant::now();
+        self.insert_at(priority, key, value, now, now)
+    }
+
+    /// Inserts an entry that started waiting at `since`, with its priority aged as of
+    /// `now`, replacing the base priority and value of an existing entry with the same key.
+    ///
+    /// Returns the previous base priority and value.
+    pub fn insert_at(
+        &mut self,
+        priority: P,
+        key: K,
+        value: V,
+        since: Instant,
+        now: Instant,
+    ) -> Option<(P, V)> {
+        let aged = self
+            .policy
+            .age(&priority, now.saturating_duration_since(since));
+        let entry = Aged {
+            base: priority,
+            since,
+            value,
+        };
+        let (_, old) = self.map.insert(aged, key, entry)?;
+        Some((old.base, old.value))
+    }
+
+    /// Recomputes the priorities of all entries as of `now`, in `O(n)`.
+    pub fn tick(&mut self, now: Instant) {
+        let policy = &self.policy;
+        self.map.reprioritize_all(|_, entry, priority| {
+            *priority = policy.age(&entry.base, now.saturating_duration_since(entry.since));
+        });
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<(P, V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let (_, entry) = self.map.remove_full(key)?;
+        Some((entry.base, entry.value))
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.map.contains_key(key)
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        Some(&self.map.get(key)?.value)
+    }
+
+    /// Returns the priority as of the last tick.
+    pub fn get_priority<Q>(&self, key: &Q) -> Option<&P>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.map.get_priority(key)
+    }
+
+    /// Returns the priority the entry was inserted with.
+    pub fn get_base_priority<Q>(&self, key: &Q) -> Option<&P>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        Some(&self.map.get(key)?.base)
+    }
+
+    /// Returns the top entry with its priority as of the last tick.
+    pub fn peek(&self) -> Option<(&P, &K, &V)> {
+        let (priority, key, entry) = self.map.peek_full()?;
+        Some((priority, key, &entry.value))
+    }
+
+    /// Pops the top entry and returns it with its base priority.
+    pub fn pop(&mut self) -> Option<(P, K, V)> {
+        let (_, key, entry) = self.map.pop_full()?;
+        Some((entry.base, key, entry.value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::AgingPriorityMap;
+
+    #[test]
+    fn tick() {
+        let start = Instant::now();
+        let secs = |secs| start + Duration::from_secs(secs);
+        let mut map = AgingPriorityMap::new(|base: &u64, waited: Duration| base + waited.as_secs());
+        map.insert_at(1, "old", (), start, start);
+        map.insert_at(3, "new", (), secs(5), secs(5));
+        assert_eq!(map.peek(), Some((&3, &"new", &())));
+        // A backdated entry is aged right away.
+        map.insert_at(2, "backdated", (), start, secs(5));
+        assert_eq!(map.peek(), Some((&7, &"backdated", &())));
+        assert_eq!(map.remove("backdated"), Some((2, ())));
+
+        map.tick(secs(6));
+        assert_eq!(map.get_priority("old"), Some(&7));
+        assert_eq!(map.get_priority("new"), Some(&4));
+        assert_eq!(map.get_base_priority("old"), Some(&1));
+        assert_eq!(map.pop(), Some((1, "old", ())));
+        assert_eq!(map.pop(), Some((3, "new", ())));
+        assert_eq!(map.pop(), None);
+    }
+}
@@ -0,0 +1,272 @@
+use crate::order::PriorityOrder;
+
+mod bucket;
+mod dary;
+mod hole;
+mod minmax;
+#[cfg(test)]
+mod model;
+mod pairing;
+#[cfg(feature = "rand")]
+mod weighted;
+
+pub use bucket::{BucketPriority, BucketQueue};
+pub use dary::DAryHeap;
+pub use minmax::MinMaxHeap;
+pub use pairing::PairingHeap;
+#[cfg(feature = "rand")]
+pub use weighted::Weighted;
+
+/// The heap that decides which entry of a [`PriorityMap`](crate::PriorityMap) pops first.
+///
+/// The map keeps keys and values in slots and indexes them by key. The backend holds one
+/// priority per slot and only has to track their order. Slots are identified by index,
+/// and removing a slot moves the last slot into its place, as with [`Vec::swap_remove`].
+///
+/// Implemented by [`DAryHeap`], [`PairingHeap`], [`BucketQueue`] and [`MinMaxHeap`], and
+/// by `Weighted` with the `rand` feature. The trait is sealed and its methods are internal
+/// to the crate.
+pub trait HeapBackend<P>: sealed::Sealed + Default {
+    /// The priorities and sequence numbers of a consumed backend, in slot order.
+    #[doc(hidden)]
+    type IntoNodes: Iterator<Item = (P, u64)>;
+
+    /// The priorities and sequence numbers of a drained backend, in slot order.
+    #[doc(hidden)]
+    type Drain<'a>: Iterator<Item = (P, u64)>
+    where
+        Self: 'a;
+
+    #[doc(hidden)]
+    fn len(&self) -> usize;
+
+    #[doc(hidden)]
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    #[doc(hidden)]
+    fn capacity(&self) -> usize;
+
+    #[doc(hidden)]
+    fn reserve(&mut self, additional: usize);
+
+    #[doc(hidden)]
+    fn shrink_to_fit(&mut self);
+
+    #[doc(hidden)]
+    fn clear(&mut self);
+
+    /// Returns the slot that pops first.
+    #[doc(hidden)]
+    fn top(&self) -> Option<usize>;
+
+    #[doc(hidden)]
+    fn priority(&self, slot: usize) -> &P;
+
+    /// Callers must restore the order of `slot` after changing the priority.
+    #[doc(hidden)]
+    fn priority_mut(&mut self, slot: usize) -> &mut P;
+
+    #[doc(hidden)]
+    fn sequence(&self, slot: usize) -> u64;
+
+    /// Calls `f` with the slots directly below `slot`, which never pop before it.
+    #[doc(hidden)]
+    fn children(&self, slot: usize, f: impl FnMut(usize));
+
+    /// Adds the priority of a new slot at the end.
+    #[doc(hidden)]
+    fn push<O: PriorityOrder<P>>(&mut self, priority: P, sequence: u64, ranking: &Ranking<O>);
+
+    /// Adds the priority of a new slot at the end, without restoring the order.
+    #[doc(hidden)]
+    fn push_unordered(&mut self, priority: P, sequence: u64);
+
+    /// Panics if the backend cannot hold `priority`, so that the map can check before it
+    /// changes anything.
+    #[doc(hidden)]
+    fn check_priority(&self, _priority: &P) {}
+
+    /// Restores the order after the priority of `slot` was raised.
+    #[doc(hidden)]
+    fn raise<O: PriorityOrder<P>>(&mut self, slot: usize, ranking: &Ranking<O>);
+
+    /// Restores the order after the priority of `slot` was lowered.
+    #[doc(hidden)]
+    fn lower<O: PriorityOrder<P>>(&mut self, slot: usize, ranking: &Ranking<O>);
+
+    /// Restores the order after the priority of `slot` changed in either direction.
+    #[doc(hidden)]
+    fn restore<O: PriorityOrder<P>>(&mut self, slot: usize, ranking: &Ranking<O>);
+
+    /// Exchanges the priorities of two slots, keeping their sequence numbers.
+    #[doc(hidden)]
+    fn swap_priorities<O: PriorityOrder<P>>(&mut self, a: usize, b: usize, ranking: &Ranking<O>);
+
+    /// Removes `slot` and moves the last slot into its place.
+    #[doc(hidden)]
+    fn remove<O: PriorityOrder<P>>(&mut self, slot: usize, ranking: &Ranking<O>) -> (P, u64);
+
+    /// Removes `slot` and moves the last slot into its place, without restoring the order.
+    #[doc(hidden)]
+    fn remove_unordered(&mut self, slot: usize) -> (P, u64);
+
+    /// Restores the order of all slots from scratch in `O(n)`.
+    #[doc(hidden)]
+    fn rebuild<O: PriorityOrder<P>>(&mut self, ranking: &Ranking<O>);
+
+    #[doc(hidden)]
+    fn drain(&mut self) -> Self::Drain<'_>;
+
+    #[doc(hidden)]
+    fn into_nodes(self) -> Self::IntoNodes;
+
+    /// Consumes the backend and returns the priorities with their slots, in pop order.
+    #[doc(hidden)]
+    fn into_sorted<O: PriorityOrder<P>>(self, ranking: &Ranking<O>) -> Vec<(P, usize)>;
+
+    /// Picks a slot with probability proportional to its [`weight`], or `None` if no
+    /// weight is positive. `sample` draws a point below the total weight.
+    #[cfg(feature = "rand")]
+    #[doc(hidden)]
+    fn weighted_slot(&self, sample: impl FnOnce(f64) -> f64) -> Option<usize>
+    where
+        P: Copy + Into<f64>,
+    {
+        scan_weighted_slot(self.len(), |slot| weight(self.priority(slot)), sample)
+    }
+}
+
+/// A [`HeapBackend`] that also finds the entry that pops last.
+///
+/// Enables [`peek_min`](crate::PriorityMap::peek_min) and
+/// [`pop_min`](crate::PriorityMap::pop_min). Implemented by [`MinMaxHeap`], and by
+/// [`DAryHeap`] with a cached bottom entry. Both peek at it in `O(1)`, but
+/// [`DAryHeap`] finds the next bottom entry in `O(n)` after popping it, so draining a map
+/// from the low end takes quadratic time unless it uses a [`MinMaxHeap`].
+pub trait DoubleEndedBackend<P>: HeapBackend<P> {
+    /// Returns the slot that pops last.
+    #[doc(hidden)]
+    fn bottom<O: PriorityOrder<P>>(&self, ranking: &Ranking<O>) -> Option<usize>;
+}
+
+mod sealed {
+    pub trait Sealed {}
+
+    impl<P, const D: usize> Sealed for super::DAryHeap<P, D> {}
+    impl<P, const N: usize> Sealed for super::BucketQueue<P, N> {}
+    impl<P> Sealed for super::PairingHeap<P> {}
+    impl<P> Sealed for super::MinMaxHeap<P> {}
+    #[cfg(feature = "rand")]
+    impl<B> Sealed for super::Weighted<B> {}
+
+    /// Compares priorities and, in stable mode, equal priorities by insertion order.
+    #[derive(Clone, Debug, Default)]
+    pub struct Ranking<O> {
+        pub order: O,
+        pub stable: bool,
+    }
+}
+
+pub(crate) use sealed::Ranking;
+
+impl<O> Ranking<O> {
+    pub(crate) fn new(order: O) -> Self {
+        Self {
+            order,
+            stable: false,
+        }
+    }
+
+    /// Returns whether the priority and sequence number `a` pop before `b`.
+    pub(crate) fn higher<P>(&self, a: (&P, u64), b: (&P, u64)) -> bool
+    where
+        O: PriorityOrder<P>,
+    {
+        if self.order.higher(a.0, b.0) {
+            return true;
+        }
+        self.stable && a.1 < b.1 && !self.order.higher(b.0, a.0)
+    }
+}
+
+/// Returns a priority as a sampling weight, treating negative, infinite and NaN priorities
+/// as zero.
+#[cfg(feature = "rand")]
+pub(crate) fn weight<P: Copy + Into<f64>>(priority: &P) -> f64 {
+    let weight: f64 = (*priority).into();
+    if weight > 0.0 && weight.is_finite() {
+        weight
+    } else {
+        0.0
+    }
+}
+
+/// Picks a slot with probability proportional to `weight_of(slot)` by scanning all `len`
+/// slots in `O(n)`, or returns `None` if no weight is positive.
+#[cfg(feature = "rand")]
+pub(crate) fn scan_weighted_slot(
+    len: usize,
+    weight_of: impl Fn(usize) -> f64,
+    sample: impl FnOnce(f64) -> f64,
+) -> Option<usize> {
+    let mut scale = 1.0;
+    let mut total: f64 = (0..len).map(&weight_of).sum();
+    if total.is_infinite() {
+        // Every weight is finite, so the sum of the weights scaled down by their count is too.
+        scale = 0.5 / len as f64;
+        total = (0..len).map(|slot| weight_of(slot) * scale).sum();
+    }
+    if total <= 0.0 {
+        return None;
+    }
+    let mut target = sample(total);
+    let mut picked = None;
+    for slot in 0..len {
+        let weight = weight_of(slot) * scale;
+        if weight > 0.0 {
+            // Rounding may leave `target` past the last weight, which then wins.
+            picked = Some(slot);
+            if target < weight {
+                break;
+            }
+            target -= weight;
+        }
+    }
+    picked
+}
+
+/// The type of slot indices and heap positions stored in the map.
+///
+/// With the `u32-index` feature, they take half the memory on 64-bit targets, but maps
+/// are limited to `u32::MAX` entries.
+#[cfg(not(feature = "u32-index"))]
+pub(crate) type Idx = usize;
+#[cfg(feature = "u32-index")]
+pub(crate) type Idx = u32;
+
+pub(crate) fn to_idx(i: usize) -> Idx {
+    Idx::try_from(i).expect("too many entries for the `u32-index` feature")
+}
+
+#[allow(clippy::unnecessary_cast)]
+pub(crate) fn from_idx(i: Idx) -> usize {
+    i as usize
+}
+
+/// Reorders `items` so that `items[i]` is the item previously at `sources[i]`, in `O(n)`
+/// swaps. Leaves `sources` unspecified.
+pub(crate) fn permute<T>(items: &mut [T], sources: &mut [usize]) {
+    for start in 0..items.len() {
+        let mut target = start;
+        while sources[target] != usize::MAX {
+            let source = std::mem::replace(&mut sources[target], usize::MAX);
+            if source == start {
+                break;
+            }
+            items.swap(target, source);
+            target = source;
+        }
+    }
+}
@@ -0,0 +1,480 @@
+use super::{from_idx, permute, to_idx, HeapBackend, Idx, Ranking};
+use crate::order::PriorityOrder;
+
+/// A small integer priority that a [`BucketQueue`] files under its own value.
+pub trait BucketPriority {
+    /// Returns the index of the bucket holding this priority.
+    fn bucket(&self) -> usize;
+}
+
+impl BucketPriority for u8 {
+    fn bucket(&self) -> usize {
+        usize::from(*self)
+    }
+}
+
+impl BucketPriority for u16 {
+    fn bucket(&self) -> usize {
+        usize::from(*self)
+    }
+}
+
+impl BucketPriority for u32 {
+    fn bucket(&self) -> usize {
+        *self as usize
+    }
+}
+
+/// A bucket queue for priorities below `N`, with one bucket per priority.
+///
+/// Inserting, removing and changing a priority are `O(1)`, and the top entry is found by
+/// scanning a bitmap of the non-empty buckets, which takes `N / 64` word operations at
+/// most. This suits schedulers with a fixed number of priority levels.
+///
+/// The order of the map must rank priorities by their value, either ascending like
+/// [`Min`](crate::Min) or descending like [`Max`](crate::Max). Operations panic on
+/// priorities of `N` or above, before the map changes.
+#[derive(Clone, Debug)]
+pub struct BucketQueue<P, const N: usize> {
+    /// The priority of every slot, linked into the list of its bucket.
+    nodes: Vec<Node<P>>,
+    /// The first and last node of every bucket, allocated on first use.
+    heads: Vec<Idx>,
+    tails: Vec<Idx>,
+    /// One bit per bucket, set if the bucket is not empty.
+    occupied: Vec<u64>,
+    top: Idx,
+    /// Whether higher buckets pop first, as last observed from the order.
+    descending: bool,
+}
+
+/// A node in the list of its bucket, kept in insertion order.
+#[derive(Clone, Debug)]
+pub struct Node<P> {
+    priority: P,
+    sequence: u64,
+    bucket: Idx,
+    prev: Idx,
+    next: Idx,
+}
+
+/// Marks a missing link between nodes.
+const NIL: Idx = Idx::MAX;
+
+impl<P, const N: usize> Default for BucketQueue<P, N> {
+    fn default() -> Self {
+        Self {
+            nodes: vec![],
+            heads: vec![],
+            tails: vec![],
+            occupied: vec![],
+            top: NIL,
+            descending: true,
+        }
+    }
+}
+
+impl<P: BucketPriority, const N: usize> BucketQueue<P, N> {
+    /// Appends the unlinked `node` to its bucket, or inserts it by sequence number if
+    /// `stable`.
+    fn link(&mut self, node: usize, stable: bool) {
+        let bucket = self.nodes[node].priority.bucket();
+        debug_assert!(bucket < N);
+        if self.heads.is_empty() {
+            self.heads = vec![NIL; N];
+            self.tails = vec![NIL; N];
+            self.occupied = vec![0; N.div_ceil(64)];
+        }
+        let sequence = self.nodes[node].sequence;
+        let mut prev = self.tails[bucket];
+        while stable && prev != NIL && self.nodes[from_idx(prev)].sequence > sequence {
+            prev = self.nodes[from_idx(prev)].prev;
+        }
+        let next = match prev {
+            NIL => self.heads[bucket],
+            prev => self.nodes[from_idx(prev)].next,
+        };
+        let this = &mut self.nodes[node];
+        this.bucket = to_idx(bucket);
+        this.prev = prev;
+        this.next = next;
+        match prev {
+            NIL => self.heads[bucket] = to_idx(node),
+            prev => self.nodes[from_idx(prev)].next = to_idx(node),
+        }
+        match next {
+            NIL => self.tails[bucket] = to_idx(node),
+            next => self.nodes[from_idx(next)].prev = to_idx(node),
+        }
+        self.occupied[bucket / 64] |= 1 << (bucket % 64);
+    }
+}
+
+impl<P, const N: usize> BucketQueue<P, N> {
+    /// Adds an unlinked node at the end.
+    fn push_node(&mut self, priority: P, sequence: u64) {
+        self.nodes.push(Node {
+            priority,
+            sequence,
+            bucket: NIL,
+            prev: NIL,
+            next: NIL,
+        });
+    }
+
+    /// Removes `node` from the list of its bucket.
+    fn unlink(&mut self, node: usize) {
+        let Node {
+            bucket, prev, next, ..
+        } = self.nodes[node];
+        let bucket = from_idx(bucket);
+        match prev {
+            NIL => self.heads[bucket] = next,
+            prev => self.nodes[from_idx(prev)].next = next,
+        }
+        match next {
+            NIL => self.tails[bucket] = prev,
+            next => self.nodes[from_idx(next)].prev = prev,
+        }
+        if self.heads[bucket] == NIL {
+            self.occupied[bucket / 64] &= !(1 << (bucket % 64));
+        }
+    }
+
+    /// Removes the unlinked `node` and moves the last node into its place.
+    fn swap_remove(&mut self, node: usize) -> Node<P> {
+        let last = self.nodes.len() - 1;
+        if node != last {
+            let Node {
+                bucket, prev, next, ..
+            } = self.nodes[last];
+            let bucket = from_idx(bucket);
+            let node = to_idx(node);
+            match prev {
+                NIL => self.heads[bucket] = node,
+                prev => self.nodes[from_idx(prev)].next = node,
+            }
+            match next {
+                NIL => self.tails[bucket] = node,
+                next => self.nodes[from_idx(next)].prev = node,
+            }
+        }
+        self.nodes.swap_remove(node)
+    }
+
+    /// Returns the first non-empty bucket at or above `from`.
+    fn occupied_from(&self, from: usize) -> Option<usize> {
+        let mut word = from / 64;
+        let mut bits = *self.occupied.get(word)? & (!0 << (from % 64));
+        loop {
+            if bits != 0 {
+                return Some(word * 64 + bits.trailing_zeros() as usize);
+            }
+            word += 1;
+            bits = *self.occupied.get(word)?;
+        }
+    }
+
+    /// Returns the last non-empty bucket below `to`.
+    fn occupied_below(&self, to: usize) -> Option<usize> {
+        let last = to.checked_sub(1)?;
+        let mut word = last / 64;
+        let mut bits = *self.occupied.get(word)? & (!0 >> (63 - last % 64));
+        loop {
+            if bits != 0 {
+                return Some(word * 64 + 63 - bits.leading_zeros() as usize);
+            }
+            word = word.checked_sub(1)?;
+            bits = self.occupied[word];
+        }
+    }
+
+    /// Returns the next non-empty bucket after `bucket` in pop order.
+    fn next_bucket(&self, bucket: usize) -> Option<usize> {
+        if self.descending {
+            self.occupied_below(bucket)
+        } else {
+            self.occupied_from(bucket + 1)
+        }
+    }
+
+    /// Finds the top node by comparing the lowest and highest non-empty bucket.
+    fn settle<O: PriorityOrder<P>>(&mut self, ranking: &Ranking<O>) {
+        if let (Some(low), Some(high)) = (self.occupied_from(0), self.occupied_below(N)) {
+            let (low, high) = (self.heads[low], self.heads[high]);
+            if low != high {
+                let (a, b) = (&self.nodes[from_idx(low)], &self.nodes[from_idx(high)]);
+                self.descending = !ranking.order.higher(&a.priority, &b.priority);
+            }
+        }
+        self.settle_known();
+    }
+
+    /// Finds the top node in the direction last observed from the order.
+    fn settle_known(&mut self) {
+        let bucket = if self.descending {
+            self.occupied_below(N)
+        } else {
+            self.occupied_from(0)
+        };
+        self.top = bucket.map_or(NIL, |bucket| self.heads[bucket]);
+    }
+
+    fn reset_buckets(&mut self) {
+        self.heads.fill(NIL);
+        self.tails.fill(NIL);
+        self.occupied.fill(0);
+        self.top = NIL;
+    }
+}
+
+impl<P: BucketPriority, const N: usize> HeapBackend<P> for BucketQueue<P, N> {
+    type IntoNodes = std::iter::Map<std::vec::IntoIter<Node<P>>, fn(Node<P>) -> (P, u64)>;
+
+    type Drain<'a>
+        = std::iter::Map<std::vec::Drain<'a, Node<P>>, fn(Node<P>) -> (P, u64)>
+    where
+        P: 'a;
+
+    fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.nodes.capacity()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.nodes.reserve(additional);
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.nodes.shrink_to_fit();
+    }
+
+    fn clear(&mut self) {
+        self.nodes.clear();
+        self.reset_buckets();
+    }
+
+    fn top(&self) -> Option<usize> {
+        (self.top != NIL).then(|| from_idx(self.top))
+    }
+
+    fn priority(&self, slot: usize) -> &P {
+        &self.nodes[slot].priority
+    }
+
+    fn priority_mut(&mut self, slot: usize) -> &mut P {
+        &mut self.nodes[slot].priority
+    }
+
+    fn sequence(&self, slot: usize) -> u64 {
+        self.nodes[slot].sequence
+    }
+
+    /// The next node in the same bucket, and for the first node of a bucket also the first
+    /// node of the next bucket.
+    fn children(&self, slot: usize, mut f: impl FnMut(usize)) {
+        let node = &self.nodes[slot];
+        if node.next != NIL {
+            f(from_idx(node.next));
+        }
+        if node.prev == NIL {
+            if let Some(bucket) = self.next_bucket(from_idx(node.bucket)) {
+                f(from_idx(self.heads[bucket]));
+            }
+        }
+    }
+
+    fn push<O: PriorityOrder<P>>(&mut self, priority: P, sequence: u64, ranking: &Ranking<O>) {
+        self.check_priority(&priority);
+        self.push_node(priority, sequence);
+        self.link(self.nodes.len() - 1, ranking.stable);
+        self.settle(ranking);
+    }
+
+    /// Links the node at the end of its bucket, so that the buckets stay consistent even
+    /// if the rebuild never comes.
+    fn push_unordered(&mut self, priority: P, sequence: u64) {
+        self.check_priority(&priority);
+        self.push_node(priority, sequence);
+        self.link(self.nodes.len() - 1, false);
+        self.settle_known();
+    }
+
+    fn check_priority(&self, priority: &P) {
+        assert!(
+            priority.bucket() < N,
+            "priority out of range for the bucket queue"
+        );
+    }
+
+    fn raise<O: PriorityOrder<P>>(&mut self, slot: usize, ranking: &Ranking<O>) {
+        self.restore(slot, ranking);
+    }
+
+    fn lower<O: PriorityOrder<P>>(&mut self, slot: usize, ranking: &Ranking<O>) {
+        self.restore(slot, ranking);
+    }
+
+    /// Leaves the node in its old bucket if the new priority is out of range.
+    fn restore<O: PriorityOrder<P>>(&mut self, slot: usize, ranking: &Ranking<O>) {
+        self.check_priority(&self.nodes[slot].priority);
+        self.unlink(slot);
+        self.link(slot, ranking.stable);
+        self.settle(ranking);
+    }
+
+    fn swap_priorities<O: PriorityOrder<P>>(&mut self, a: usize, b: usize, ranking: &Ranking<O>) {
+        let (low, high) = self.nodes.split_at_mut(a.max(b));
+        std::mem::swap(&mut low[a.min(b)].priority, &mut high[0].priority);
+        self.restore(a, ranking);
+        self.restore(b, ranking);
+    }
+
+    fn remove<O: PriorityOrder<P>>(&mut self, slot: usize, ranking: &Ranking<O>) -> (P, u64) {
+        self.unlink(slot);
+        let node = self.swap_remove(slot);
+        self.settle(ranking);
+        (node.priority, node.sequence)
+    }
+
+    fn remove_unordered(&mut self, slot: usize) -> (P, u64) {
+        self.unlink(slot);
+        let node = self.swap_remove(slot);
+        self.settle_known();
+        (node.priority, node.sequence)
+    }
+
+    /// Refills the buckets from scratch, in `O(n log n)` if the ranking is stable to sort
+    /// the nodes by sequence number first.
+    fn rebuild<O: PriorityOrder<P>>(&mut self, ranking: &Ranking<O>) {
+        for node in &self.nodes {
+            self.check_priority(&node.priority);
+        }
+        self.reset_buckets();
+        let mut slots: Vec<_> = (0..self.nodes.len()).collect();
+        if ranking.stable {
+            slots.sort_unstable_by_key(|&slot| self.nodes[slot].sequence);
+        }
+        for slot in slots {
+            self.link(slot, false);
+        }
+        self.settle(ranking);
+    }
+
+    fn drain(&mut self) -> Self::Drain<'_> {
+        self.reset_buckets();
+        self.nodes
+            .drain(..)
+            .map(|node| (node.priority, node.sequence))
+    }
+
+    fn into_nodes(self) -> Self::IntoNodes {
+        self.nodes
+            .into_iter()
+            .map(|node| (node.priority, node.sequence))
+    }
+
+    /// Walks the buckets in pop order, then moves the nodes into that order.
+    fn into_sorted<O: PriorityOrder<P>>(mut self, ranking: &Ranking<O>) -> Vec<(P, usize)> {
+        self.settle(ranking);
+        let mut sorted = Vec::with_capacity(self.nodes.len());
+        let mut bucket = self.top().map(|top| from_idx(self.nodes[top].bucket));
+        while let Some(current) = bucket {
+            let mut node = self.heads[current];
+            while node != NIL {
+                sorted.push(from_idx(node));
+                node = self.nodes[from_idx(node)].next;
+            }
+            bucket = self.next_bucket(current);
+        }
+        let mut sources = sorted.clone();
+        permute(&mut self.nodes, &mut sources);
+        self.nodes
+            .into_iter()
+            .zip(sorted)
+            .map(|(node, slot)| (node.priority, slot))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hash_map::RandomState;
+
+    use super::BucketQueue;
+    use crate::backend::model::check_backend;
+    use crate::{BucketPriorityMap, Min};
+
+    #[test]
+    fn insert_pop() {
+        let mut map: BucketPriorityMap<u8, _, _, 64> = BucketPriorityMap::default();
+        for i in 0..100 {
+            map.insert((i * 37 % 64) as u8, i, ());
+        }
+        assert_eq!(map.peek_full().map(|(priority, _, _)| *priority), Some(63));
+        assert_eq!(map.reprioritize(&5, 0), Some((5 * 37 % 64) as u8));
+        let popped: Vec<_> = std::iter::from_fn(|| map.pop_full())
+            .map(|(priority, _, _)| priority)
+            .collect();
+        assert_eq!(popped.len(), 100);
+        assert!(popped.windows(2).all(|pair| pair[0] >= pair[1]));
+        assert_eq!(popped.last(), Some(&0));
+    }
+
+    #[test]
+    fn min_order() {
+        let mut map: BucketPriorityMap<u16, _, _, 1000, RandomState, Min> =
+            (0..500).map(|i| (999 - 2 * i, i, ())).collect();
+        assert_eq!(map.pop_full(), Some((1, 499, ())));
+        map.insert(0, 1000, ());
+        map.insert(999, 1001, ());
+        let sorted: Vec<_> = map
+            .iter_sorted()
+            .map(|(priority, _, _)| *priority)
+            .collect();
+        assert_eq!(sorted.len(), 501);
+        assert_eq!(&sorted[..3], [0, 3, 5]);
+        assert!(sorted.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(map.into_sorted_vec().last(), Some(&(999, 1001, ())));
+    }
+
+    #[test]
+    #[should_panic = "priority out of range"]
+    fn out_of_range() {
+        let mut map: BucketPriorityMap<u8, _, _, 64> = BucketPriorityMap::default();
+        map.insert(64, "a", ());
+    }
+
+    #[test]
+    fn out_of_range_keeps_map_usable() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let mut map: BucketPriorityMap<u8, _, _, 16> = BucketPriorityMap::default();
+        map.insert(3, "a", ());
+        assert!(catch_unwind(AssertUnwindSafe(|| map.insert(200, "b", ()))).is_err());
+        assert!(catch_unwind(AssertUnwindSafe(|| map.reprioritize("a", 16))).is_err());
+        assert!(catch_unwind(AssertUnwindSafe(
+            || map.extend([(5, "c", ()), (99, "d", ())])
+        ))
+        .is_err());
+        assert_eq!(map.len(), 2);
+        assert!(!map.contains_key("b"));
+        assert_eq!(map.get_priority("a"), Some(&3));
+
+        map.insert(7, "e", ());
+        assert_eq!(map.pop_full(), Some((7, "e", ())));
+        assert_eq!(map.pop_full(), Some((5, "c", ())));
+        assert_eq!(map.pop_full(), Some((3, "a", ())));
+        assert_eq!(map.pop_full(), None);
+    }
+
+    #[test]
+    fn matches_model() {
+        check_backend::<BucketQueue<_, 1000>>(1000);
+        // Few priorities, so that buckets hold many entries in insertion order.
+        check_backend::<BucketQueue<_, 4>>(4);
+    }
+}
@@ -0,0 +1,389 @@
+use super::hole::Hole;
+use super::{from_idx, to_idx, DoubleEndedBackend, HeapBackend, Idx, Ranking};
+use crate::order::PriorityOrder;
+
+/// An implicit heap in which every node has `D` children, a binary heap by default.
+///
+/// This is the default backend of [`PriorityMap`](crate::PriorityMap). Higher arities make
+/// the heap shallower, which speeds up sifting for large maps at the cost of more
+/// comparisons per level, see [`into_arity`](crate::PriorityMap::into_arity).
+///
+/// The nodes only hold priorities and slot indices, so sifting moves small fixed-size
+/// records regardless of the size of the keys and values.
+///
+/// The entry that pops last is cached, so [`peek_min`](crate::PriorityMap::peek_min) is
+/// `O(1)`. Removing it or raising its priority scans the leaves for the next one in
+/// `O(n)`.
+#[derive(Clone, Debug)]
+pub struct DAryHeap<P, const D: usize = 2> {
+    /// Priorities in heap order, each pointing to its slot.
+    nodes: Vec<Node<P>>,
+    /// The heap position of every slot.
+    positions: Vec<Idx>,
+    /// The slot that pops last, always a leaf.
+    bottom: Option<usize>,
+}
+
+/// A priority in the heap.
+#[derive(Clone, Debug)]
+pub struct Node<P> {
+    priority: P,
+    sequence: u64,
+    /// The index of the slot holding the key and value.
+    slot: Idx,
+}
+
+impl<P> Node<P> {
+    fn slot(&self) -> usize {
+        from_idx(self.slot)
+    }
+
+    fn rank(&self) -> (&P, u64) {
+        (&self.priority, self.sequence)
+    }
+}
+
+impl<P, const D: usize> Default for DAryHeap<P, D> {
+    fn default() -> Self {
+        Self {
+            nodes: vec![],
+            positions: vec![],
+            bottom: None,
+        }
+    }
+}
+
+impl<P, const D: usize> DAryHeap<P, D> {
+    fn position(&self, slot: usize) -> usize {
+        from_idx(self.positions[slot])
+    }
+
+    /// Removes the node at `position` and moves the last node into its place, without
+    /// restoring the heap property there.
+    fn swap_remove(&mut self, position: usize) -> Node<P> {
+        let node = self.nodes.swap_remove(position);
+        if let Some(moved) = self.nodes.get(position) {
+            self.positions[moved.slot()] = to_idx(position);
+        }
+        let last = self.positions.len() - 1;
+        self.positions.swap_remove(node.slot());
+        if node.slot() != last {
+            // The last slot moves into the slot of the removed node.
+            let moved = self.position(node.slot());
+            self.nodes[moved].slot = node.slot;
+        }
+        node
+    }
+
+    /// Moves the node at `position` up or down, whichever restores the heap property.
+    fn resift<O: PriorityOrder<P>>(&mut self, position: usize, ranking: &Ranking<O>) {
+        if self.swim_up(position, ranking) == position {
+            self.sink_down(position, ranking);
+        }
+    }
+
+    fn swim_up<O: PriorityOrder<P>>(&mut self, position: usize, ranking: &Ranking<O>) -> usize {
+        const { assert!(D >= 2, "heap arity must be at least 2") };
+        let mut hole = Hole::new(&mut self.nodes, position);
+        while hole.position() > 0 {
+            let parent = (hole.position() - 1) / D;
+            if !ranking.higher(hole.element().rank(), hole.get(parent).rank()) {
+                break;
+            }
+            self.positions[hole.get(parent).slot()] = to_idx(hole.position());
+            hole.move_to(parent);
+        }
+        self.positions[hole.element().slot()] = to_idx(hole.position());
+        hole.position()
+    }
+
+    fn sink_down<O: PriorityOrder<P>>(&mut self, position: usize, ranking: &Ranking<O>) {
+        let mut hole = Hole::new(&mut self.nodes, position);
+        while let Some(child) = higher_child::<_, _, D>(&hole, ranking) {
+            self.positions[hole.get(child).slot()] = to_idx(hole.position());
+            hole.move_to(child);
+        }
+        self.positions[hole.element().slot()] = to_idx(hole.position());
+    }
+
+    /// Makes `slot` the bottom if its priority is now the lowest.
+    fn lowered<O: PriorityOrder<P>>(&mut self, slot: usize, ranking: &Ranking<O>) {
+        let node = &self.nodes[self.position(slot)];
+        match self.bottom {
+            Some(bottom)
+                if !ranking.higher(self.nodes[self.position(bottom)].rank(), node.rank()) => {}
+            _ => self.bottom = Some(slot),
+        }
+    }
+
+    /// Finds the bottom among the leaves from scratch.
+    fn find_bottom<O: PriorityOrder<P>>(&mut self, ranking: &Ranking<O>) {
+        let first_leaf = (self.nodes.len() + D - 2) / D;
+        self.bottom = self.nodes[first_leaf.min(self.nodes.len())..]
+            .iter()
+            .reduce(|bottom, node| {
+                if ranking.higher(bottom.rank(), node.rank()) {
+                    node
+                } else {
+                    bottom
+                }
+            })
+            .map(Node::slot);
+    }
+
+    /// Reorders the nodes so that every node is at the index of its slot, in `O(n)` swaps.
+    fn align(&mut self) {
+        self.bottom = None;
+        for position in 0..self.nodes.len() {
+            while self.nodes[position].slot() != position {
+                let slot = self.nodes[position].slot();
+                self.nodes.swap(position, slot);
+            }
+        }
+        self.positions.clear();
+    }
+}
+
+impl<P, const D: usize> HeapBackend<P> for DAryHeap<P, D> {
+    type IntoNodes = std::iter::Map<std::vec::IntoIter<Node<P>>, fn(Node<P>) -> (P, u64)>;
+
+    type Drain<'a>
+        = std::iter::Map<std::vec::Drain<'a, Node<P>>, fn(Node<P>) -> (P, u64)>
+    where
+        P: 'a;
+
+    fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.nodes.capacity().min(self.positions.capacity())
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.nodes.reserve(additional);
+        self.positions.reserve(additional);
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.nodes.shrink_to_fit();
+        self.positions.shrink_to_fit();
+    }
+
+    fn clear(&mut self) {
+        self.nodes.clear();
+        self.positions.clear();
+        self.bottom = None;
+    }
+
+    fn top(&self) -> Option<usize> {
+        Some(self.nodes.first()?.slot())
+    }
+
+    fn priority(&self, slot: usize) -> &P {
+        &self.nodes[self.position(slot)].priority
+    }
+
+    fn priority_mut(&mut self, slot: usize) -> &mut P {
+        let position = self.position(slot);
+        &mut self.nodes[position].priority
+    }
+
+    fn sequence(&self, slot: usize) -> u64 {
+        self.nodes[self.position(slot)].sequence
+    }
+
+    fn children(&self, slot: usize, mut f: impl FnMut(usize)) {
+        let first = D * self.position(slot) + 1;
+        for child in self.nodes.iter().skip(first).take(D) {
+            f(child.slot());
+        }
+    }
+
+    fn push<O: PriorityOrder<P>>(&mut self, priority: P, sequence: u64, ranking: &Ranking<O>) {
+        self.push_unordered(priority, sequence);
+        self.swim_up(self.nodes.len() - 1, ranking);
+        self.lowered(self.nodes.len() - 1, ranking);
+    }
+
+    fn push_unordered(&mut self, priority: P, sequence: u64) {
+        let slot = self.nodes.len();
+        self.nodes.push(Node {
+            priority,
+            sequence,
+            slot: to_idx(slot),
+        });
+        self.positions.push(to_idx(slot));
+    }
+
+    fn raise<O: PriorityOrder<P>>(&mut self, slot: usize, ranking: &Ranking<O>) {
+        self.swim_up(self.position(slot), ranking);
+        if self.bottom == Some(slot) {
+            self.find_bottom(ranking);
+        }
+    }
+
+    fn lower<O: PriorityOrder<P>>(&mut self, slot: usize, ranking: &Ranking<O>) {
+        self.sink_down(self.position(slot), ranking);
+        self.lowered(slot, ranking);
+    }
+
+    fn restore<O: PriorityOrder<P>>(&mut self, slot: usize, ranking: &Ranking<O>) {
+        self.resift(self.position(slot), ranking);
+        if self.bottom == Some(slot) {
+            self.find_bottom(ranking);
+        } else {
+            self.lowered(slot, ranking);
+        }
+    }
+
+    /// Since the heap positions keep their priorities, no sifting is needed unless the
+    /// ranking is stable.
+    fn swap_priorities<O: PriorityOrder<P>>(&mut self, a: usize, b: usize, ranking: &Ranking<O>) {
+        let (position_a, position_b) = (self.position(a), self.position(b));
+        // Swap the slots and sequence numbers between the two nodes, not the priorities.
+        let (low, high) = self.nodes.split_at_mut(position_a.max(position_b));
+        let (low, high) = (&mut low[position_a.min(position_b)], &mut high[0]);
+        std::mem::swap(&mut low.slot, &mut high.slot);
+        std::mem::swap(&mut low.sequence, &mut high.sequence);
+        self.positions.swap(a, b);
+        if ranking.stable {
+            // Ties between the swapped entries may now be out of insertion order.
+            self.resift(position_a, ranking);
+            self.resift(self.position(a), ranking);
+            self.find_bottom(ranking);
+        } else if self.bottom == Some(a) {
+            self.bottom = Some(b);
+        } else if self.bottom == Some(b) {
+            self.bottom = Some(a);
+        }
+    }
+
+    fn remove<O: PriorityOrder<P>>(&mut self, slot: usize, ranking: &Ranking<O>) -> (P, u64) {
+        let position = self.position(slot);
+        let node = self.swap_remove(position);
+        if position < self.nodes.len() {
+            // The last node was moved here and may come from a different subtree.
+            self.resift(position, ranking);
+        }
+        if self.bottom == Some(slot) {
+            self.find_bottom(ranking);
+        } else if self.bottom == Some(self.nodes.len()) {
+            // The last slot moved into the removed one.
+            self.bottom = Some(slot);
+        }
+        (node.priority, node.sequence)
+    }
+
+    fn remove_unordered(&mut self, slot: usize) -> (P, u64) {
+        self.bottom = None;
+        let node = self.swap_remove(self.position(slot));
+        (node.priority, node.sequence)
+    }
+
+    fn rebuild<O: PriorityOrder<P>>(&mut self, ranking: &Ranking<O>) {
+        let first_leaf = (self.nodes.len() + D - 2) / D;
+        for position in (0..first_leaf).rev() {
+            sink_down_unindexed::<_, _, D>(&mut self.nodes, position, ranking);
+        }
+        for (position, node) in self.nodes.iter().enumerate() {
+            self.positions[node.slot()] = to_idx(position);
+        }
+        self.find_bottom(ranking);
+    }
+
+    fn drain(&mut self) -> Self::Drain<'_> {
+        self.align();
+        self.nodes
+            .drain(..)
+            .map(|node| (node.priority, node.sequence))
+    }
+
+    fn into_nodes(mut self) -> Self::IntoNodes {
+        self.align();
+        self.nodes
+            .into_iter()
+            .map(|node| (node.priority, node.sequence))
+    }
+
+    /// Heapsorts the nodes in place.
+    fn into_sorted<O: PriorityOrder<P>>(self, ranking: &Ranking<O>) -> Vec<(P, usize)> {
+        let mut nodes = self.nodes;
+        for end in (1..nodes.len()).rev() {
+            nodes.swap(0, end);
+            sink_down_unindexed::<_, _, D>(&mut nodes[..end], 0, ranking);
+        }
+        nodes.reverse();
+        nodes
+            .into_iter()
+            .map(|node| {
+                let slot = node.slot();
+                (node.priority, slot)
+            })
+            .collect()
+    }
+}
+
+impl<P, const D: usize> DoubleEndedBackend<P> for DAryHeap<P, D> {
+    fn bottom<O: PriorityOrder<P>>(&self, _ranking: &Ranking<O>) -> Option<usize> {
+        self.bottom
+    }
+}
+
+/// Restores the heap property below `position` without recording positions.
+fn sink_down_unindexed<P, O: PriorityOrder<P>, const D: usize>(
+    nodes: &mut [Node<P>],
+    position: usize,
+    ranking: &Ranking<O>,
+) {
+    let mut hole = Hole::new(nodes, position);
+    while let Some(child) = higher_child::<_, _, D>(&hole, ranking) {
+        hole.move_to(child);
+    }
+}
+
+/// Returns the child of the hole in a `D`-ary heap that must be moved up into it, if any.
+fn higher_child<P, O: PriorityOrder<P>, const D: usize>(
+    hole: &Hole<'_, Node<P>>,
+    ranking: &Ranking<O>,
+) -> Option<usize> {
+    const { assert!(D >= 2, "heap arity must be at least 2") };
+    let first = D * hole.position() + 1;
+    if first >= hole.len() {
+        return None;
+    }
+    let mut child = first;
+    for other in first + 1..(first + D).min(hole.len()) {
+        if ranking.higher(hole.get(other).rank(), hole.get(child).rank()) {
+            child = other;
+        }
+    }
+    ranking
+        .higher(hole.get(child).rank(), hole.element().rank())
+        .then_some(child)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::model::check_double_ended_backend;
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn index_size() {
+        let (idx, node) = if cfg!(feature = "u32-index") {
+            (4, 16)
+        } else {
+            (8, 24)
+        };
+        assert_eq!(std::mem::size_of::<Idx>(), idx);
+        assert_eq!(std::mem::size_of::<Node<u32>>(), node);
+    }
+
+    #[test]
+    fn bottom() {
+        check_double_ended_backend::<DAryHeap<_>>(1000);
+        // Few priorities, so that ties are common.
+        check_double_ended_backend::<DAryHeap<_, 4>>(4);
+    }
+}
@@ -0,0 +1,69 @@
+use std::mem::ManuallyDrop;
+use std::ptr;
+
+/// An element lifted out of a slice, leaving a hole that other elements can be moved into.
+///
+/// When dropped, the element is written back into the hole at its current position.
+pub(super) struct Hole<'a, T> {
+    data: &'a mut [T],
+    element: ManuallyDrop<T>,
+    position: usize,
+}
+
+impl<'a, T> Hole<'a, T> {
+    /// Lifts the element at `position` out of `data`.
+    ///
+    /// Panics if `position` is out of bounds.
+    pub fn new(data: &'a mut [T], position: usize) -> Self {
+        assert!(position < data.len());
+        // SAFETY: `position` is in bounds, and the duplicate left behind is never read or
+        // dropped before it is overwritten.
+        let element = unsafe { ptr::read(&data[position]) };
+        Self {
+            data,
+            element: ManuallyDrop::new(element),
+            position,
+        }
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// The lifted element.
+    pub fn element(&self) -> &T {
+        &self.element
+    }
+
+    /// Returns the element at `index`, which must not be the hole.
+    pub fn get(&self, index: usize) -> &T {
+        assert!(index != self.position);
+        &self.data[index]
+    }
+
+    /// Moves the element at `index` into the hole, which then moves to `index`.
+    pub fn move_to(&mut self, index: usize) {
+        assert!(index != self.position && index < self.data.len());
+        // SAFETY: both positions are in bounds and distinct. The element at `index` is
+        // duplicated until the hole is filled by another move or on drop.
+        unsafe {
+            let base = self.data.as_mut_ptr();
+            ptr::copy_nonoverlapping(base.add(index), base.add(self.position), 1);
+        }
+        self.position = index;
+    }
+}
+
+impl<T> Drop for Hole<'_, T> {
+    fn drop(&mut self) {
+        // SAFETY: the hole holds a stale duplicate, which is overwritten without being dropped.
+        unsafe {
+            let element = ManuallyDrop::take(&mut self.element);
+            ptr::write(&mut self.data[self.position], element);
+        }
+    }
+}
@@ -0,0 +1,393 @@
+use super::{from_idx, to_idx, DoubleEndedBackend, HeapBackend, Idx, Ranking};
+use crate::order::PriorityOrder;
+
+/// A min-max heap, which finds both the entry that pops first and the one that pops last.
+///
+/// Nodes on even levels rank higher than all their descendants and nodes on odd levels
+/// lower, so the top is the root and the bottom one of its children. Both ends pop in
+/// `O(log n)`, with about twice the comparisons of a binary [`DAryHeap`](super::DAryHeap)
+/// per level.
+#[derive(Clone, Debug)]
+pub struct MinMaxHeap<P> {
+    /// Priorities in heap order, each pointing to its slot.
+    nodes: Vec<Node<P>>,
+    /// The heap position of every slot.
+    positions: Vec<Idx>,
+}
+
+/// A priority in the heap.
+#[derive(Clone, Debug)]
+pub struct Node<P> {
+    priority: P,
+    sequence: u64,
+    /// The index of the slot holding the key and value.
+    slot: Idx,
+}
+
+impl<P> Node<P> {
+    fn slot(&self) -> usize {
+        from_idx(self.slot)
+    }
+
+    fn rank(&self) -> (&P, u64) {
+        (&self.priority, self.sequence)
+    }
+}
+
+impl<P> Default for MinMaxHeap<P> {
+    fn default() -> Self {
+        Self {
+            nodes: vec![],
+            positions: vec![],
+        }
+    }
+}
+
+/// Returns whether nodes at `position` rank higher than their descendants.
+fn is_top_level(position: usize) -> bool {
+    (position + 1).ilog2().is_multiple_of(2)
+}
+
+impl<P> MinMaxHeap<P> {
+    fn position(&self, slot: usize) -> usize {
+        from_idx(self.positions[slot])
+    }
+
+    /// Returns whether the node at `a` belongs above the node at `b`, on a top level if
+    /// `top` and on a bottom level otherwise.
+    fn above<O: PriorityOrder<P>>(
+        &self,
+        a: usize,
+        b: usize,
+        top: bool,
+        ranking: &Ranking<O>,
+    ) -> bool {
+        let (a, b) = (self.nodes[a].rank(), self.nodes[b].rank());
+        if top {
+            ranking.higher(a, b)
+        } else {
+            ranking.higher(b, a)
+        }
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.nodes.swap(a, b);
+        self.positions[self.nodes[a].slot()] = to_idx(a);
+        self.positions[self.nodes[b].slot()] = to_idx(b);
+    }
+
+    /// Moves the node at `position` to its place, in either direction.
+    fn resift<O: PriorityOrder<P>>(&mut self, position: usize, ranking: &Ranking<O>) {
+        self.swim_up(position, ranking);
+        // A parent swapped down from a bottom level may rank below the subtree.
+        self.sink_down(position, ranking);
+    }
+
+    fn swim_up<O: PriorityOrder<P>>(&mut self, position: usize, ranking: &Ranking<O>) {
+        if position == 0 {
+            return;
+        }
+        let parent = (position - 1) / 2;
+        let top = is_top_level(position);
+        if self.above(parent, position, top, ranking) {
+            // The node belongs on the levels of its parent.
+            self.swap(position, parent);
+            self.swim_up_levels(parent, !top, ranking);
+        } else {
+            self.swim_up_levels(position, top, ranking);
+        }
+    }
+
+    /// Swims up through the grandparents, which are on the same kind of level.
+    fn swim_up_levels<O: PriorityOrder<P>>(
+        &mut self,
+        mut position: usize,
+        top: bool,
+        ranking: &Ranking<O>,
+    ) {
+        while position > 2 {
+            let grandparent = ((position - 1) / 2 - 1) / 2;
+            if !self.above(position, grandparent, top, ranking) {
+                break;
+            }
+            self.swap(position, grandparent);
+            position = grandparent;
+        }
+    }
+
+    fn sink_down<O: PriorityOrder<P>>(&mut self, mut position: usize, ranking: &Ranking<O>) {
+        let top = is_top_level(position);
+        loop {
+            let first = 2 * position + 1;
+            let descendants = [
+                first,
+                first + 1,
+                2 * first + 1,
+                2 * first + 2,
+                2 * first + 3,
+                2 * first + 4,
+            ];
+            let Some(best) = descendants
+                .into_iter()
+                .take_while(|&descendant| descendant < self.nodes.len())
+                .reduce(|best, descendant| {
+                    if self.above(descendant, best, top, ranking) {
+                        descendant
+                    } else {
+                        best
+                    }
+                })
+            else {
+                return;
+            };
+            if !self.above(best, position, top, ranking) {
+                return;
+            }
+            self.swap(best, position);
+            if best <= first + 1 {
+                return;
+            }
+            // The node moved down two levels, past a parent on the other kind of level.
+            let parent = (best - 1) / 2;
+            if self.above(parent, best, top, ranking) {
+                self.swap(best, parent);
+            }
+            position = best;
+        }
+    }
+
+    /// Removes the node at `position` and moves the last node into its place, without
+    /// restoring the heap property there.
+    fn swap_remove(&mut self, position: usize) -> Node<P> {
+        let node = self.nodes.swap_remove(position);
+        if let Some(moved) = self.nodes.get(position) {
+            self.positions[moved.slot()] = to_idx(position);
+        }
+        let last = self.positions.len() - 1;
+        self.positions.swap_remove(node.slot());
+        if node.slot() != last {
+            // The last slot moves into the slot of the removed node.
+            let moved = self.position(node.slot());
+            self.nodes[moved].slot = node.slot;
+        }
+        node
+    }
+
+    /// Reorders the nodes so that every node is at the index of its slot, in `O(n)` swaps.
+    fn align(&mut self) {
+        for position in 0..self.nodes.len() {
+            while self.nodes[position].slot() != position {
+                let slot = self.nodes[position].slot();
+                self.nodes.swap(position, slot);
+            }
+        }
+        self.positions.clear();
+    }
+}
+
+impl<P> HeapBackend<P> for MinMaxHeap<P> {
+    type IntoNodes = std::iter::Map<std::vec::IntoIter<Node<P>>, fn(Node<P>) -> (P, u64)>;
+
+    type Drain<'a>
+        = std::iter::Map<std::vec::Drain<'a, Node<P>>, fn(Node<P>) -> (P, u64)>
+    where
+        P: 'a;
+
+    fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.nodes.capacity().min(self.positions.capacity())
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.nodes.reserve(additional);
+        self.positions.reserve(additional);
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.nodes.shrink_to_fit();
+        self.positions.shrink_to_fit();
+    }
+
+    fn clear(&mut self) {
+        self.nodes.clear();
+        self.positions.clear();
+    }
+
+    fn top(&self) -> Option<usize> {
+        Some(self.nodes.first()?.slot())
+    }
+
+    fn priority(&self, slot: usize) -> &P {
+        &self.nodes[self.position(slot)].priority
+    }
+
+    fn priority_mut(&mut self, slot: usize) -> &mut P {
+        let position = self.position(slot);
+        &mut self.nodes[position].priority
+    }
+
+    fn sequence(&self, slot: usize) -> u64 {
+        self.nodes[self.position(slot)].sequence
+    }
+
+    /// Nodes on top levels rank below their top-level grandparent, and nodes on bottom
+    /// levels below their parent, so these form a tree.
+    fn children(&self, slot: usize, mut f: impl FnMut(usize)) {
+        let position = self.position(slot);
+        if !is_top_level(position) {
+            return;
+        }
+        let first = 2 * position + 1;
+        for child in first..first + 2 {
+            let grandchildren = 2 * child + 1..2 * child + 3;
+            for descendant in std::iter::once(child).chain(grandchildren) {
+                if let Some(node) = self.nodes.get(descendant) {
+                    f(node.slot());
+                }
+            }
+        }
+    }
+
+    fn push<O: PriorityOrder<P>>(&mut self, priority: P, sequence: u64, ranking: &Ranking<O>) {
+        self.push_unordered(priority, sequence);
+        self.swim_up(self.nodes.len() - 1, ranking);
+    }
+
+    fn push_unordered(&mut self, priority: P, sequence: u64) {
+        let slot = self.nodes.len();
+        self.nodes.push(Node {
+            priority,
+            sequence,
+            slot: to_idx(slot),
+        });
+        self.positions.push(to_idx(slot));
+    }
+
+    fn raise<O: PriorityOrder<P>>(&mut self, slot: usize, ranking: &Ranking<O>) {
+        self.resift(self.position(slot), ranking);
+    }
+
+    fn lower<O: PriorityOrder<P>>(&mut self, slot: usize, ranking: &Ranking<O>) {
+        self.resift(self.position(slot), ranking);
+    }
+
+    fn restore<O: PriorityOrder<P>>(&mut self, slot: usize, ranking: &Ranking<O>) {
+        self.resift(self.position(slot), ranking);
+    }
+
+    fn swap_priorities<O: PriorityOrder<P>>(&mut self, a: usize, b: usize, ranking: &Ranking<O>) {
+        let (position_a, position_b) = (self.position(a), self.position(b));
+        // Swap the slots and sequence numbers between the two nodes, not the priorities.
+        let (low, high) = self.nodes.split_at_mut(position_a.max(position_b));
+        let (low, high) = (&mut low[position_a.min(position_b)], &mut high[0]);
+        std::mem::swap(&mut low.slot, &mut high.slot);
+        std::mem::swap(&mut low.sequence, &mut high.sequence);
+        self.positions.swap(a, b);
+        if ranking.stable {
+            // Ties between the swapped entries may now be out of insertion order.
+            self.resift(position_a, ranking);
+            self.resift(self.position(a), ranking);
+        }
+    }
+
+    fn remove<O: PriorityOrder<P>>(&mut self, slot: usize, ranking: &Ranking<O>) -> (P, u64) {
+        let position = self.position(slot);
+        let node = self.swap_remove(position);
+        if position < self.nodes.len() {
+            self.resift(position, ranking);
+        }
+        (node.priority, node.sequence)
+    }
+
+    fn remove_unordered(&mut self, slot: usize) -> (P, u64) {
+        let node = self.swap_remove(self.position(slot));
+        (node.priority, node.sequence)
+    }
+
+    /// Sinks every inner node from the last one up, in `O(n)`.
+    fn rebuild<O: PriorityOrder<P>>(&mut self, ranking: &Ranking<O>) {
+        for (position, node) in self.nodes.iter().enumerate() {
+            self.positions[node.slot()] = to_idx(position);
+        }
+        for position in (0..self.nodes.len() / 2).rev() {
+            self.sink_down(position, ranking);
+        }
+    }
+
+    fn drain(&mut self) -> Self::Drain<'_> {
+        self.align();
+        self.nodes
+            .drain(..)
+            .map(|node| (node.priority, node.sequence))
+    }
+
+    fn into_nodes(mut self) -> Self::IntoNodes {
+        self.align();
+        self.nodes
+            .into_iter()
+            .map(|node| (node.priority, node.sequence))
+    }
+
+    fn into_sorted<O: PriorityOrder<P>>(mut self, ranking: &Ranking<O>) -> Vec<(P, usize)> {
+        let mut sorted = Vec::with_capacity(self.nodes.len());
+        while !self.nodes.is_empty() {
+            // Keep the slots of the remaining nodes, which are only looked up by position.
+            let node = self.nodes.swap_remove(0);
+            if !self.nodes.is_empty() {
+                self.sink_down(0, ranking);
+            }
+            let slot = node.slot();
+            sorted.push((node.priority, slot));
+        }
+        sorted
+    }
+}
+
+impl<P> DoubleEndedBackend<P> for MinMaxHeap<P> {
+    fn bottom<O: PriorityOrder<P>>(&self, ranking: &Ranking<O>) -> Option<usize> {
+        let position = match self.nodes.len() {
+            0 => return None,
+            1 | 2 => self.nodes.len() - 1,
+            _ if ranking.higher(self.nodes[1].rank(), self.nodes[2].rank()) => 2,
+            _ => 1,
+        };
+        Some(self.nodes[position].slot())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MinMaxHeap;
+    use crate::backend::model::check_double_ended_backend;
+    use crate::DoubleEndedPriorityMap;
+
+    #[test]
+    fn pop_both_ends() {
+        let mut map: DoubleEndedPriorityMap<_, _, _> = (0..10).map(|i| (i, i, i * 10)).collect();
+        assert_eq!(map.peek_min_full(), Some((&0, &0, &0)));
+        assert_eq!(map.pop_min(), Some(0));
+        assert_eq!(map.pop(), Some(90));
+        map.insert(-1, 10, 100);
+        assert_eq!(map.pop_min_full(), Some((-1, 10, 100)));
+        assert_eq!(map.reprioritize(&5, 20), Some(5));
+        let sorted: Vec<_> = map.iter_sorted().map(|(_, key, _)| *key).collect();
+        assert_eq!(sorted, [5, 8, 7, 6, 4, 3, 2, 1]);
+        assert_eq!(map.clone().into_sorted_vec()[0], (20, 5, 50));
+
+        let mut map = map.into_backend::<crate::MinMaxHeap<_>>();
+        map.set_stable(true);
+        assert_eq!(map.pop_min(), Some(10));
+        assert_eq!(map.pop_min(), Some(20));
+        assert_eq!(map.peek(), Some(&50));
+    }
+
+    #[test]
+    fn matches_model() {
+        check_double_ended_backend::<MinMaxHeap<_>>(1000);
+        // Few priorities, so that ties are common.
+        check_double_ended_backend::<MinMaxHeap<_>>(4);
+    }
+}
@@ -0,0 +1,141 @@
+//! Checks backends against a model of the map under random operations.
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use super::{DoubleEndedBackend, HeapBackend};
+use crate::{Max, PriorityMap};
+
+type Map<B> = PriorityMap<u32, u32, (), RandomState, Max, B>;
+
+/// Sorts a priority and sequence number into pop order.
+fn rank((priority, sequence): (u32, u64)) -> (u32, u64) {
+    (u32::MAX - priority, sequence)
+}
+
+/// The priority and sequence number of every key, as a stable max-map assigns them.
+#[derive(Default)]
+struct Model {
+    entries: HashMap<u32, (u32, u64)>,
+    next_sequence: u64,
+}
+
+impl Model {
+    fn insert(&mut self, key: u32, priority: u32) -> Option<u32> {
+        match self.entries.get_mut(&key) {
+            Some(entry) => Some(std::mem::replace(&mut entry.0, priority)),
+            None => {
+                self.entries.insert(key, (priority, self.next_sequence));
+                self.next_sequence += 1;
+                None
+            }
+        }
+    }
+
+    fn reprioritize(&mut self, key: u32, priority: u32) -> Option<u32> {
+        let entry = self.entries.get_mut(&key)?;
+        Some(std::mem::replace(&mut entry.0, priority))
+    }
+
+    fn remove(&mut self, key: u32) -> Option<u32> {
+        Some(self.entries.remove(&key)?.0)
+    }
+
+    /// Returns the entry that pops first: the highest priority, inserted first.
+    fn top(&self) -> Option<(u32, u32)> {
+        let (&key, &(priority, _)) = self.entries.iter().min_by_key(|(_, &entry)| rank(entry))?;
+        Some((priority, key))
+    }
+
+    /// Returns the entry that pops last: the lowest priority, inserted last.
+    fn bottom(&self) -> Option<(u32, u32)> {
+        let (&key, &(priority, _)) = self.entries.iter().max_by_key(|(_, &entry)| rank(entry))?;
+        Some((priority, key))
+    }
+
+    /// Returns the entries in pop order.
+    fn sorted(&self) -> Vec<(u32, u32, ())> {
+        let mut sorted: Vec<_> = self.entries.iter().collect();
+        sorted.sort_by_key(|(_, &entry)| rank(entry));
+        sorted
+            .into_iter()
+            .map(|(&key, &(priority, _))| (priority, key, ()))
+            .collect()
+    }
+
+    fn truncate_top(&mut self, len: usize) {
+        for (_, key, ()) in self.sorted().into_iter().skip(len) {
+            self.entries.remove(&key);
+        }
+    }
+}
+
+/// Runs random operations on a stable map with backend `B` and on the model, with
+/// priorities below `priorities`, and checks that they agree after every operation.
+///
+/// `extra` runs instead of one of the operations, for checks specific to the backend.
+fn check<B, F>(priorities: u32, mut extra: F)
+where
+    B: HeapBackend<u32>,
+    F: FnMut(&mut Map<B>, &mut Model),
+{
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut map: Map<B> = PriorityMap::new().into_backend();
+    map.set_stable(true);
+    let mut model = Model::default();
+    for _ in 0..3000 {
+        let key = rng.gen_range(0..64);
+        let priority = rng.gen_range(0..priorities);
+        match rng.gen_range(0..7) {
+            0 | 1 => assert_eq!(
+                map.insert(priority, key, ()).map(|(priority, ())| priority),
+                model.insert(key, priority)
+            ),
+            2 => assert_eq!(
+                map.reprioritize(&key, priority),
+                model.reprioritize(key, priority)
+            ),
+            3 => assert_eq!(
+                map.remove_full(&key).map(|(priority, ())| priority),
+                model.remove(key)
+            ),
+            4 => {
+                let top = model.top();
+                if let Some((_, key)) = top {
+                    model.remove(key);
+                }
+                assert_eq!(map.pop_full().map(|(p, k, ())| (p, k)), top);
+            }
+            5 => {
+                let len = rng.gen_range(0..48);
+                map.truncate_top(len);
+                model.truncate_top(len);
+            }
+            _ => extra(&mut map, &mut model),
+        }
+        assert_eq!(map.len(), model.entries.len());
+        assert_eq!(map.peek_full().map(|(p, k, ())| (*p, *k)), model.top());
+    }
+    assert_eq!(map.into_sorted_vec(), model.sorted());
+}
+
+/// Checks backend `B` against the model, with priorities below `priorities`.
+pub(crate) fn check_backend<B: HeapBackend<u32>>(priorities: u32) {
+    check::<B, _>(priorities, |_, _| {});
+}
+
+/// Checks backend `B` against the model like [`check_backend`], including the entry that
+/// pops last.
+pub(crate) fn check_double_ended_backend<B: DoubleEndedBackend<u32>>(priorities: u32) {
+    check::<B, _>(priorities, |map, model| {
+        let bottom = model.bottom();
+        assert_eq!(map.peek_min_full().map(|(p, k, ())| (*p, *k)), bottom);
+        if let Some((_, key)) = bottom {
+            model.remove(key);
+        }
+        assert_eq!(map.pop_min_full().map(|(p, k, ())| (p, k)), bottom);
+    });
+}
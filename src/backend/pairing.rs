@@ -0,0 +1,406 @@
+use super::{from_idx, permute, to_idx, HeapBackend, Idx, Ranking};
+use crate::order::PriorityOrder;
+
+/// A pairing heap, a heap-ordered tree in which node `i` holds the priority of slot `i`.
+///
+/// Raising a priority only cuts the subtree of its node and links it to the root, in
+/// `O(1)`. Popping and lowering a priority are `O(log n)` amortized, but slower in practice
+/// than with a [`DAryHeap`](super::DAryHeap), so this backend pays off for workloads
+/// dominated by raised priorities, such as Dijkstra's algorithm with a [`Min`](crate::Min)
+/// order.
+#[derive(Clone, Debug)]
+pub struct PairingHeap<P> {
+    nodes: Vec<Node<P>>,
+    root: Idx,
+}
+
+/// A node in the pairing heap, linked to its first child and its next sibling.
+#[derive(Clone, Debug)]
+pub struct Node<P> {
+    priority: P,
+    sequence: u64,
+    child: Idx,
+    sibling: Idx,
+    /// The previous sibling, or the parent for a first child.
+    prev: Idx,
+}
+
+impl<P> Node<P> {
+    fn rank(&self) -> (&P, u64) {
+        (&self.priority, self.sequence)
+    }
+}
+
+/// Marks a missing link between nodes.
+const NIL: Idx = Idx::MAX;
+
+impl<P> Default for PairingHeap<P> {
+    fn default() -> Self {
+        Self {
+            nodes: vec![],
+            root: NIL,
+        }
+    }
+}
+
+impl<P> PairingHeap<P> {
+    /// Removes the detached `node` and moves the last node into its place.
+    fn swap_remove(&mut self, node: usize) -> Node<P> {
+        let last = self.nodes.len() - 1;
+        if node != last {
+            let Node {
+                child,
+                sibling,
+                prev,
+                ..
+            } = self.nodes[last];
+            let node = to_idx(node);
+            if prev != NIL {
+                let prev = &mut self.nodes[from_idx(prev)];
+                if from_idx(prev.child) == last {
+                    prev.child = node;
+                } else {
+                    prev.sibling = node;
+                }
+            }
+            if sibling != NIL {
+                self.nodes[from_idx(sibling)].prev = node;
+            }
+            if child != NIL {
+                self.nodes[from_idx(child)].prev = node;
+            }
+            if from_idx(self.root) == last {
+                self.root = node;
+            }
+        }
+        self.nodes.swap_remove(node)
+    }
+
+    /// Detaches `node` from the tree and merges its children back in, leaving it as a
+    /// single node outside the tree.
+    fn unlink<O: PriorityOrder<P>>(&mut self, node: usize, ranking: &Ranking<O>) {
+        let child = std::mem::replace(&mut self.nodes[node].child, NIL);
+        let children = self.merge_pairs(child, ranking);
+        if to_idx(node) == self.root {
+            self.root = children;
+        } else {
+            self.cut(node);
+            self.root = self.link(self.root, children, ranking);
+        }
+    }
+
+    /// Cuts the subtree of the non-root `node` from its parent.
+    fn cut(&mut self, node: usize) {
+        let Node { sibling, prev, .. } = self.nodes[node];
+        let prev_node = &mut self.nodes[from_idx(prev)];
+        if from_idx(prev_node.child) == node {
+            prev_node.child = sibling;
+        } else {
+            prev_node.sibling = sibling;
+        }
+        if sibling != NIL {
+            self.nodes[from_idx(sibling)].prev = prev;
+        }
+        self.nodes[node].sibling = NIL;
+        self.nodes[node].prev = NIL;
+    }
+
+    /// Links two trees, returning the new root. Either may be `NIL`.
+    fn link<O: PriorityOrder<P>>(&mut self, a: Idx, b: Idx, ranking: &Ranking<O>) -> Idx {
+        if a == NIL {
+            return b;
+        }
+        if b == NIL {
+            return a;
+        }
+        let higher = ranking.higher(
+            self.nodes[from_idx(b)].rank(),
+            self.nodes[from_idx(a)].rank(),
+        );
+        let (parent, child) = if higher { (b, a) } else { (a, b) };
+        let first = self.nodes[from_idx(parent)].child;
+        if first != NIL {
+            self.nodes[from_idx(first)].prev = child;
+        }
+        let child_node = &mut self.nodes[from_idx(child)];
+        child_node.sibling = first;
+        child_node.prev = parent;
+        self.nodes[from_idx(parent)].child = child;
+        parent
+    }
+
+    /// Merges the sibling list starting at `first` into a single tree with the standard
+    /// two-pass pairing, returning its root.
+    fn merge_pairs<O: PriorityOrder<P>>(&mut self, first: Idx, ranking: &Ranking<O>) -> Idx {
+        // Link pairs from left to right, stacking the results through their sibling links.
+        let mut stack = NIL;
+        let mut next = first;
+        while next != NIL {
+            let a = next;
+            let b = self.nodes[from_idx(a)].sibling;
+            next = if b == NIL {
+                NIL
+            } else {
+                self.nodes[from_idx(b)].sibling
+            };
+            self.detach_root(a);
+            self.detach_root(b);
+            let tree = self.link(a, b, ranking);
+            self.nodes[from_idx(tree)].sibling = stack;
+            stack = tree;
+        }
+        // Link the stacked trees from right to left.
+        let mut root = NIL;
+        while stack != NIL {
+            let tree = stack;
+            stack = self.nodes[from_idx(tree)].sibling;
+            self.nodes[from_idx(tree)].sibling = NIL;
+            root = self.link(root, tree, ranking);
+        }
+        root
+    }
+
+    /// Clears the sibling and parent links of `node`, if it is not `NIL`.
+    fn detach_root(&mut self, node: Idx) {
+        if node != NIL {
+            let node = &mut self.nodes[from_idx(node)];
+            node.sibling = NIL;
+            node.prev = NIL;
+        }
+    }
+}
+
+impl<P> HeapBackend<P> for PairingHeap<P> {
+    type IntoNodes = std::iter::Map<std::vec::IntoIter<Node<P>>, fn(Node<P>) -> (P, u64)>;
+
+    type Drain<'a>
+        = std::iter::Map<std::vec::Drain<'a, Node<P>>, fn(Node<P>) -> (P, u64)>
+    where
+        P: 'a;
+
+    fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.nodes.capacity()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.nodes.reserve(additional);
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.nodes.shrink_to_fit();
+    }
+
+    fn clear(&mut self) {
+        self.nodes.clear();
+        self.root = NIL;
+    }
+
+    fn top(&self) -> Option<usize> {
+        (self.root != NIL).then(|| from_idx(self.root))
+    }
+
+    fn priority(&self, slot: usize) -> &P {
+        &self.nodes[slot].priority
+    }
+
+    fn priority_mut(&mut self, slot: usize) -> &mut P {
+        &mut self.nodes[slot].priority
+    }
+
+    fn sequence(&self, slot: usize) -> u64 {
+        self.nodes[slot].sequence
+    }
+
+    fn children(&self, slot: usize, mut f: impl FnMut(usize)) {
+        let mut child = self.nodes[slot].child;
+        while child != NIL {
+            f(from_idx(child));
+            child = self.nodes[from_idx(child)].sibling;
+        }
+    }
+
+    fn push<O: PriorityOrder<P>>(&mut self, priority: P, sequence: u64, ranking: &Ranking<O>) {
+        self.push_unordered(priority, sequence);
+        self.root = self.link(self.root, to_idx(self.nodes.len() - 1), ranking);
+    }
+
+    /// Adds the node outside the tree, until the next rebuild.
+    fn push_unordered(&mut self, priority: P, sequence: u64) {
+        self.nodes.push(Node {
+            priority,
+            sequence,
+            child: NIL,
+            sibling: NIL,
+            prev: NIL,
+        });
+    }
+
+    fn raise<O: PriorityOrder<P>>(&mut self, slot: usize, ranking: &Ranking<O>) {
+        if to_idx(slot) != self.root {
+            self.cut(slot);
+            self.root = self.link(self.root, to_idx(slot), ranking);
+        }
+    }
+
+    fn lower<O: PriorityOrder<P>>(&mut self, slot: usize, ranking: &Ranking<O>) {
+        self.unlink(slot, ranking);
+        self.root = self.link(self.root, to_idx(slot), ranking);
+    }
+
+    fn restore<O: PriorityOrder<P>>(&mut self, slot: usize, ranking: &Ranking<O>) {
+        self.lower(slot, ranking);
+    }
+
+    fn swap_priorities<O: PriorityOrder<P>>(&mut self, a: usize, b: usize, ranking: &Ranking<O>) {
+        let (low, high) = self.nodes.split_at_mut(a.max(b));
+        std::mem::swap(&mut low[a.min(b)].priority, &mut high[0].priority);
+        self.restore(a, ranking);
+        self.restore(b, ranking);
+    }
+
+    fn remove<O: PriorityOrder<P>>(&mut self, slot: usize, ranking: &Ranking<O>) -> (P, u64) {
+        self.unlink(slot, ranking);
+        let node = self.swap_remove(slot);
+        (node.priority, node.sequence)
+    }
+
+    /// Drops the tree without fixing any links, which the next rebuild resets anyway.
+    fn remove_unordered(&mut self, slot: usize) -> (P, u64) {
+        self.root = NIL;
+        let node = self.nodes.swap_remove(slot);
+        (node.priority, node.sequence)
+    }
+
+    /// Links all nodes from scratch with a single round of two-pass pairing.
+    fn rebuild<O: PriorityOrder<P>>(&mut self, ranking: &Ranking<O>) {
+        let len = self.nodes.len();
+        for (i, node) in self.nodes.iter_mut().enumerate() {
+            node.child = NIL;
+            node.prev = NIL;
+            node.sibling = if i + 1 < len { to_idx(i + 1) } else { NIL };
+        }
+        let first = if len > 0 { to_idx(0) } else { NIL };
+        self.root = self.merge_pairs(first, ranking);
+    }
+
+    fn drain(&mut self) -> Self::Drain<'_> {
+        self.root = NIL;
+        self.nodes
+            .drain(..)
+            .map(|node| (node.priority, node.sequence))
+    }
+
+    fn into_nodes(self) -> Self::IntoNodes {
+        self.nodes
+            .into_iter()
+            .map(|node| (node.priority, node.sequence))
+    }
+
+    /// Takes the root off the tree until it is empty, then moves the nodes into that order.
+    fn into_sorted<O: PriorityOrder<P>>(mut self, ranking: &Ranking<O>) -> Vec<(P, usize)> {
+        let mut sorted = Vec::with_capacity(self.nodes.len());
+        while self.root != NIL {
+            let root = from_idx(self.root);
+            sorted.push(root);
+            let child = std::mem::replace(&mut self.nodes[root].child, NIL);
+            self.root = self.merge_pairs(child, ranking);
+        }
+        let mut sources = sorted.clone();
+        permute(&mut self.nodes, &mut sources);
+        self.nodes
+            .into_iter()
+            .zip(sorted)
+            .map(|(node, slot)| (node.priority, slot))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hash_map::RandomState;
+
+    use super::PairingHeap;
+    use crate::backend::model::check_backend;
+    use crate::{Min, PairingPriorityMap};
+
+    #[test]
+    fn insert_pop() {
+        let mut map: PairingPriorityMap<_, _, _> = PairingPriorityMap::default();
+        for i in 0..100 {
+            map.insert(i * 37 % 100, i, i.to_string());
+        }
+        assert_eq!(map.len(), 100);
+        assert_eq!(map.insert(1000, 5, "five".into()), Some((85, "5".into())));
+        assert_eq!(map.peek_full(), Some((&1000, &5, &"five".into())));
+        let popped: Vec<_> = std::iter::from_fn(|| map.pop_full())
+            .map(|(priority, _, _)| priority)
+            .collect();
+        assert_eq!(popped.len(), 100);
+        assert_eq!(popped[0], 1000);
+        assert!(popped.windows(2).all(|pair| pair[0] >= pair[1]));
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn reprioritize() {
+        let mut map: PairingPriorityMap<_, _, _, RandomState, Min> =
+            (0..50).map(|i| (i, i, ())).collect();
+        assert_eq!(map.reprioritize(&40, -1), Some(40));
+        assert_eq!(map.reprioritize(&0, 100), Some(0));
+        assert_eq!(map.reprioritize(&99, 1), None);
+        assert_eq!(map.pop_full(), Some((-1, 40, ())));
+        assert_eq!(map.pop_full(), Some((1, 1, ())));
+        assert_eq!(map.remove_full(&2), Some((2, ())));
+        assert_eq!(map.get_priority(&0), Some(&100));
+        assert_eq!(std::iter::from_fn(|| map.pop()).count(), 47);
+    }
+
+    #[test]
+    fn append() {
+        let mut a: PairingPriorityMap<_, _, _> = (0..10).map(|i| (i, i, 'a')).collect();
+        let mut b: PairingPriorityMap<_, _, _> = (5..15).map(|i| (-i, i, 'b')).collect();
+        a.append(&mut b);
+        assert!(b.is_empty());
+        assert_eq!(a.len(), 15);
+        assert_eq!(a.get(&7), Some(&'b'));
+        assert_eq!(a.get_priority(&7), Some(&-7));
+        let popped: Vec<_> = std::iter::from_fn(|| a.pop_full())
+            .map(|(_, key, _)| key)
+            .collect();
+        assert_eq!(popped, [4, 3, 2, 1, 0, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14]);
+    }
+
+    #[test]
+    fn matches_model() {
+        check_backend::<PairingHeap<_>>(1000);
+        // Few priorities, so that ties are common.
+        check_backend::<PairingHeap<_>>(4);
+    }
+
+    #[test]
+    fn bulk_operations() {
+        let mut map: PairingPriorityMap<_, _, _> = (0..30).map(|i| (i % 10, i, i)).collect();
+        map.set_stable(true);
+        let sorted: Vec<_> = map.iter_sorted().map(|(_, key, _)| *key).collect();
+        assert_eq!(&sorted[..6], [9, 19, 29, 8, 18, 28]);
+
+        let extracted: Vec<_> = map.extract_if(|_, key, _| key % 3 == 0).collect();
+        assert_eq!(extracted.len(), 10);
+        assert!(map.swap_priorities(&1, &29));
+        assert_eq!(map.get_priority(&1), Some(&9));
+        if let Some(mut top) = map.peek_mut() {
+            *top.priority_mut() = 0;
+        }
+        map.truncate_top(5);
+        let keys: Vec<_> = map
+            .into_sorted_vec()
+            .into_iter()
+            .map(|(_, key, _)| key)
+            .collect();
+        assert_eq!(keys, [19, 8, 28, 7, 17]);
+    }
+}
@@ -0,0 +1,392 @@
+use super::{scan_weighted_slot, weight, DoubleEndedBackend, HeapBackend, Ranking};
+use crate::order::PriorityOrder;
+
+/// A backend that keeps a cumulative-weight index next to another backend `B`.
+///
+/// The index is a Fenwick tree over the priorities as weights, updated with every change
+/// in `O(log n)`, so that [`pop_weighted`](crate::PriorityMap::pop_weighted) picks an
+/// entry in `O(log n)` instead of scanning all of them. Negative, infinite and NaN
+/// priorities weigh nothing. If the weights add up to more than `f64::MAX`, or to nothing,
+/// picking falls back to an `O(n)` scan.
+#[derive(Clone, Debug)]
+pub struct Weighted<B> {
+    heap: B,
+    /// The weight of every slot, as last added to the tree.
+    weights: Vec<f64>,
+    /// The Fenwick tree: node `i` (counting from one) sums the weights of slots
+    /// `i - lowbit(i)` up to but excluding `i`.
+    tree: Vec<f64>,
+}
+
+impl<B: Default> Default for Weighted<B> {
+    fn default() -> Self {
+        Self {
+            heap: B::default(),
+            weights: vec![],
+            tree: vec![],
+        }
+    }
+}
+
+fn lowbit(i: usize) -> usize {
+    i & i.wrapping_neg()
+}
+
+impl<B> Weighted<B> {
+    /// Adds `delta` to the weight of `slot` in the tree.
+    fn add(&mut self, slot: usize, delta: f64) {
+        let mut i = slot + 1;
+        while i <= self.tree.len() {
+            self.tree[i - 1] += delta;
+            i += lowbit(i);
+        }
+    }
+
+    fn set(&mut self, slot: usize, weight: f64) {
+        let delta = weight - std::mem::replace(&mut self.weights[slot], weight);
+        self.add(slot, delta);
+    }
+
+    /// Appends the weight of a new last slot.
+    fn push_weight(&mut self, weight: f64) {
+        let i = self.tree.len() + 1;
+        let mut sum = weight;
+        let mut j = i - 1;
+        while j > i - lowbit(i) {
+            sum += self.tree[j - 1];
+            j -= lowbit(j);
+        }
+        self.weights.push(weight);
+        self.tree.push(sum);
+    }
+
+    /// Moves the weight of the last slot into `slot`, mirroring the backend.
+    fn remove_weight(&mut self, slot: usize) {
+        let last = self.weights.len() - 1;
+        if slot != last {
+            self.set(slot, self.weights[last]);
+        }
+        // No other node covers the last slot.
+        self.weights.pop();
+        self.tree.pop();
+    }
+
+    /// Rebuilds the tree from the weights in `O(n)`, which also clears rounding errors.
+    fn rebuild_tree(&mut self) {
+        self.tree.clone_from(&self.weights);
+        for i in 1..=self.tree.len() {
+            let parent = i + lowbit(i);
+            if parent <= self.tree.len() {
+                self.tree[parent - 1] += self.tree[i - 1];
+            }
+        }
+    }
+
+    fn total(&self) -> f64 {
+        let mut i = self.tree.len();
+        let mut sum = 0.0;
+        while i > 0 {
+            sum += self.tree[i - 1];
+            i -= lowbit(i);
+        }
+        sum
+    }
+
+    /// Returns the slot whose weight covers the cumulative weight `target`.
+    fn find(&self, mut target: f64) -> usize {
+        let len = self.tree.len();
+        let mut slot = 0;
+        let mut step = if len == 0 { 0 } else { 1 << len.ilog2() };
+        while step > 0 {
+            if slot + step <= len && self.tree[slot + step - 1] <= target {
+                slot += step;
+                target -= self.tree[slot - 1];
+            }
+            step >>= 1;
+        }
+        slot
+    }
+}
+
+impl<P, B> HeapBackend<P> for Weighted<B>
+where
+    P: Copy + Into<f64>,
+    B: HeapBackend<P>,
+{
+    type IntoNodes = B::IntoNodes;
+    type Drain<'a>
+        = B::Drain<'a>
+    where
+        Self: 'a;
+
+    fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.heap.capacity()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.heap.reserve(additional);
+        self.weights.reserve(additional);
+        self.tree.reserve(additional);
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.heap.shrink_to_fit();
+        self.weights.shrink_to_fit();
+        self.tree.shrink_to_fit();
+    }
+
+    fn clear(&mut self) {
+        self.heap.clear();
+        self.weights.clear();
+        self.tree.clear();
+    }
+
+    fn top(&self) -> Option<usize> {
+        self.heap.top()
+    }
+
+    fn priority(&self, slot: usize) -> &P {
+        self.heap.priority(slot)
+    }
+
+    fn priority_mut(&mut self, slot: usize) -> &mut P {
+        self.heap.priority_mut(slot)
+    }
+
+    fn sequence(&self, slot: usize) -> u64 {
+        self.heap.sequence(slot)
+    }
+
+    fn children(&self, slot: usize, f: impl FnMut(usize)) {
+        self.heap.children(slot, f);
+    }
+
+    fn push<O: PriorityOrder<P>>(&mut self, priority: P, sequence: u64, ranking: &Ranking<O>) {
+        self.push_weight(weight(&priority));
+        self.heap.push(priority, sequence, ranking);
+    }
+
+    fn push_unordered(&mut self, priority: P, sequence: u64) {
+        self.push_weight(weight(&priority));
+        self.heap.push_unordered(priority, sequence);
+    }
+
+    fn check_priority(&self, priority: &P) {
+        self.heap.check_priority(priority);
+    }
+
+    fn raise<O: PriorityOrder<P>>(&mut self, slot: usize, ranking: &Ranking<O>) {
+        self.set(slot, weight(self.heap.priority(slot)));
+        self.heap.raise(slot, ranking);
+    }
+
+    fn lower<O: PriorityOrder<P>>(&mut self, slot: usize, ranking: &Ranking<O>) {
+        self.set(slot, weight(self.heap.priority(slot)));
+        self.heap.lower(slot, ranking);
+    }
+
+    fn restore<O: PriorityOrder<P>>(&mut self, slot: usize, ranking: &Ranking<O>) {
+        self.set(slot, weight(self.heap.priority(slot)));
+        self.heap.restore(slot, ranking);
+    }
+
+    fn swap_priorities<O: PriorityOrder<P>>(&mut self, a: usize, b: usize, ranking: &Ranking<O>) {
+        let (weight_a, weight_b) = (self.weights[a], self.weights[b]);
+        self.set(a, weight_b);
+        self.set(b, weight_a);
+        self.heap.swap_priorities(a, b, ranking);
+    }
+
+    fn remove<O: PriorityOrder<P>>(&mut self, slot: usize, ranking: &Ranking<O>) -> (P, u64) {
+        self.remove_weight(slot);
+        self.heap.remove(slot, ranking)
+    }
+
+    fn remove_unordered(&mut self, slot: usize) -> (P, u64) {
+        self.remove_weight(slot);
+        self.heap.remove_unordered(slot)
+    }
+
+    fn rebuild<O: PriorityOrder<P>>(&mut self, ranking: &Ranking<O>) {
+        self.weights.clear();
+        let weights = (0..self.heap.len()).map(|slot| weight(self.heap.priority(slot)));
+        self.weights.extend(weights);
+        self.rebuild_tree();
+        self.heap.rebuild(ranking);
+    }
+
+    fn drain(&mut self) -> Self::Drain<'_> {
+        self.weights.clear();
+        self.tree.clear();
+        self.heap.drain()
+    }
+
+    fn into_nodes(self) -> Self::IntoNodes {
+        self.heap.into_nodes()
+    }
+
+    fn into_sorted<O: PriorityOrder<P>>(self, ranking: &Ranking<O>) -> Vec<(P, usize)> {
+        self.heap.into_sorted(ranking)
+    }
+
+    fn weighted_slot(&self, sample: impl FnOnce(f64) -> f64) -> Option<usize> {
+        let total = self.total();
+        if total.is_infinite() || total <= 0.0 {
+            // An overflow leaves the tree infinite and rounding may cancel it down to zero
+            // until the next rebuild, but the weights themselves are exact.
+            return scan_weighted_slot(self.weights.len(), |slot| self.weights[slot], sample);
+        }
+        let slot = self.find(sample(total));
+        // Rounding may land past the end or on a slot without weight, so take the nearest
+        // slot with weight instead.
+        (slot..self.weights.len())
+            .chain((0..slot.min(self.weights.len())).rev())
+            .find(|&slot| self.weights[slot] > 0.0)
+    }
+}
+
+impl<P, B> DoubleEndedBackend<P> for Weighted<B>
+where
+    P: Copy + Into<f64>,
+    B: DoubleEndedBackend<P>,
+{
+    fn bottom<O: PriorityOrder<P>>(&self, ranking: &Ranking<O>) -> Option<usize> {
+        self.heap.bottom(ranking)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::Weighted;
+    use crate::backend::model::check_double_ended_backend;
+    use crate::backend::{weight, DAryHeap, HeapBackend, MinMaxHeap, Ranking};
+    use crate::{Max, WeightedPriorityMap};
+
+    /// Checks that the tree matches one rebuilt from the priorities.
+    fn assert_consistent<P: Copy + Into<f64>>(backend: &Weighted<DAryHeap<P>>) {
+        let weights: Vec<_> = (0..backend.len())
+            .map(|slot| weight(backend.priority(slot)))
+            .collect();
+        assert_eq!(backend.weights, weights);
+        let mut expected = Weighted::<DAryHeap<P>> {
+            weights,
+            ..Default::default()
+        };
+        expected.rebuild_tree();
+        for (actual, expected) in backend.tree.iter().zip(&expected.tree) {
+            assert!((actual - expected).abs() < 1e-6, "{actual} != {expected}");
+        }
+        assert_eq!(backend.tree.len(), expected.tree.len());
+    }
+
+    #[test]
+    fn tracks_mutations() {
+        let ranking = Ranking::new(Max);
+        let mut backend = Weighted::<DAryHeap<u32>>::default();
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..2000 {
+            let len = backend.len();
+            match rng.gen_range(0..6) {
+                0 | 1 => backend.push(rng.gen_range(0..32), 0, &ranking),
+                2 if len > 0 => {
+                    let slot = rng.gen_range(0..len);
+                    *backend.priority_mut(slot) = rng.gen_range(0..32);
+                    backend.restore(slot, &ranking);
+                }
+                3 if len > 0 => {
+                    backend.remove(rng.gen_range(0..len), &ranking);
+                }
+                4 if len > 1 => {
+                    // The map never swaps a slot with itself.
+                    let a = rng.gen_range(0..len);
+                    let b = (a + rng.gen_range(1..len)) % len;
+                    backend.swap_priorities(a, b, &ranking);
+                }
+                5 => {
+                    backend.push_unordered(rng.gen_range(0..32), 0);
+                    backend.rebuild(&ranking);
+                }
+                _ => {}
+            }
+            assert_consistent(&backend);
+        }
+    }
+
+    #[test]
+    fn matches_model() {
+        check_double_ended_backend::<Weighted<MinMaxHeap<_>>>(1000);
+    }
+
+    #[test]
+    fn finds_slots_by_cumulative_weight() {
+        let ranking = Ranking::new(Max);
+        let mut backend = Weighted::<DAryHeap<u32>>::default();
+        for priority in [2, 0, 3, 0, 5] {
+            backend.push(priority, 0, &ranking);
+        }
+        let picks: Vec<_> = [0.0, 1.9, 2.0, 4.9, 5.0, 9.9, 10.0]
+            .into_iter()
+            .map(|target| {
+                backend.weighted_slot(|total| {
+                    assert_eq!(total, 10.0);
+                    target
+                })
+            })
+            .collect();
+        let slots = [0, 0, 2, 2, 4, 4, 4];
+        assert_eq!(picks, slots.map(Some));
+    }
+
+    #[test]
+    fn pop_weighted() {
+        let mut rng = rand::rngs::mock::StepRng::new(0, 1 << 60);
+        let mut map: WeightedPriorityMap<_, _, _> = [(1u32, "a", ()), (0, "b", ()), (4, "c", ())]
+            .into_iter()
+            .collect();
+        let mut popped = vec![];
+        while let Some((_, key, ())) = map.pop_weighted(&mut rng) {
+            popped.push(key);
+        }
+        popped.sort();
+        assert_eq!(popped, ["a", "c"]);
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn removes_infinite_weight() {
+        let mut rng = rand::rngs::mock::StepRng::new(0, 1 << 60);
+        let mut map: WeightedPriorityMap<f64, _, _> =
+            [(1.0, "a", ()), (f64::INFINITY, "inf", ()), (2.0, "b", ())]
+                .into_iter()
+                .collect();
+        map.remove("inf");
+        let mut popped = vec![];
+        while let Some((_, key, ())) = map.pop_weighted(&mut rng) {
+            popped.push(key);
+        }
+        popped.sort();
+        assert_eq!(popped, ["a", "b"]);
+    }
+
+    #[test]
+    fn overflowing_weights() {
+        let mut rng = rand::rngs::mock::StepRng::new(0, 1 << 60);
+        let mut map: WeightedPriorityMap<f64, _, _> = [(f64::MAX, "a", ()), (f64::MAX, "b", ())]
+            .into_iter()
+            .collect();
+        map.insert(1.0, "c", ());
+        let mut popped = vec![];
+        while let Some((_, key, ())) = map.pop_weighted(&mut rng) {
+            popped.push(key);
+        }
+        popped.sort();
+        assert_eq!(popped, ["a", "b", "c"]);
+    }
+}
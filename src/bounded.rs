@@ -0,0 +1,307 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+
+use crate::backend::{DoubleEndedBackend, MinMaxHeap};
+use crate::evict::{Discard, Evict};
+use crate::order::{Max, PriorityOrder};
+use crate::PriorityMap;
+
+/// A [`PriorityMap`] that holds at most `max_len` entries.
+///
+/// Inserting into a full map evicts the entry that pops last to make room, or rejects the
+/// new entry if it would pop last itself. This keeps the top `max_len` entries of a stream,
+/// as for top-k queries or cache admission. The backend finds the entry that pops last,
+/// see [`DoubleEndedBackend`].
+///
+/// Entries displaced by [`Extend`] and [`set_max_len`](Self::set_max_len) go to the
+/// [`Evict`] hook `E`. [`insert`](Self::insert) never calls the hook: it returns the entry
+/// it evicts or replaces, and the entry it rejects, to the caller instead.
+pub struct BoundedPriorityMap<P, K, V, S = RandomState, O = Max, B = MinMaxHeap<P>, E = Discard>
+where
+    K: Hash,
+{
+    map: PriorityMap<P, K, V, S, O, B>,
+    max_len: usize,
+    evict: E,
+}
+
+/// The error returned when a full [`BoundedPriorityMap`] rejects an entry.
+///
+/// Contains the rejected entry.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CapacityError<T>(pub T);
+
+impl<T> fmt::Display for CapacityError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("map is full and the entry would pop last")
+    }
+}
+
+impl<T: fmt::Debug> std::error::Error for CapacityError<T> {}
+
+impl<P, K, V> BoundedPriorityMap<P, K, V>
+where
+    P: PartialOrd,
+    K: Eq + Hash,
+{
+    pub fn new(max_len: usize) -> Self {
+        Self::with_order(max_len, Max)
+    }
+}
+
+impl<P, K, V, O> BoundedPriorityMap<P, K, V, RandomState, O>
+where
+    K: Eq + Hash,
+    O: PriorityOrder<P>,
+{
+    pub fn with_order(max_len: usize, order: O) -> Self {
+        Self {
+            map: PriorityMap::with_order(order).into_backend(),
+            max_len,
+            evict: Discard,
+        }
+    }
+}
+
+impl<P, K, V, S, O, B, E> BoundedPriorityMap<P, K, V, S, O, B, E>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+    B: DoubleEndedBackend<P>,
+{
+    /// Replaces the hook that receives the entries displaced by [`Extend`] and
+    /// [`set_max_len`](Self::set_max_len), but not by [`insert`](Self::insert).
+    pub fn on_evict<F>(self, evict: F) -> BoundedPriorityMap<P, K, V, S, O, B, F>
+    where
+        F: Evict<P, K, V>,
+    {
+        BoundedPriorityMap {
+            map: self.map,
+            max_len: self.max_len,
+            evict,
+        }
+    }
+
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    /// Changes the maximum length, evicting the entries that pop last until the map fits.
+    pub fn set_max_len(&mut self, max_len: usize)
+    where
+        E: Evict<P, K, V>,
+    {
+        self.max_len = max_len;
+        while self.map.len() > max_len {
+            let (priority, key, value) = self.map.pop_min_full().expect("map is not empty");
+            self.evict.evict(priority, key, value);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.map.len() >= self.max_len
+    }
+
+    /// Returns whether an entry with `priority` and a new key would be inserted.
+    pub fn admits(&self, priority: &P) -> bool {
+        if !self.is_full() {
+            return true;
+        }
+        self.map
+            .peek_min_full()
+            .is_some_and(|(min, _, _)| self.map.order().higher(priority, min))
+    }
+
+    /// Inserts an entry and returns the entry that left the map to make room for it.
+    ///
+    /// An existing key keeps its place and gets the new priority and value, returning the
+    /// old ones. A new key evicts the entry that pops last if the map is full. If the new
+    /// entry would pop last itself, it is rejected instead.
+    ///
+    /// The displaced entry is returned rather than passed to the [`Evict`] hook.
+    #[allow(clippy::type_complexity)]
+    pub fn insert(
+        &mut self,
+        priority: P,
+        key: K,
+        value: V,
+    ) -> Result<Option<(P, K, V)>, CapacityError<(P, K, V)>> {
+        let (priority, key, value) = match self.map.replace_full(priority, key, value) {
+            Ok(replaced) => return Ok(Some(replaced)),
+            Err(entry) => entry,
+        };
+        if !self.admits(&priority) {
+            return Err(CapacityError((priority, key, value)));
+        }
+        let evicted = if self.is_full() {
+            self.map.pop_min_full()
+        } else {
+            None
+        };
+        self.map.insert(priority, key, value);
+        Ok(evicted)
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<(P, V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.map.remove_full(key)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.map.contains_key(key)
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.map.get(key)
+    }
+
+    pub fn get_priority<Q>(&self, key: &Q) -> Option<&P>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.map.get_priority(key)
+    }
+
+    pub fn peek(&self) -> Option<(&P, &K, &V)> {
+        self.map.peek_full()
+    }
+
+    /// Returns the entry that pops last, which is evicted next.
+    pub fn peek_min(&self) -> Option<(&P, &K, &V)> {
+        self.map.peek_min_full()
+    }
+
+    pub fn pop(&mut self) -> Option<(P, K, V)> {
+        self.map.pop_full()
+    }
+
+    pub fn pop_min(&mut self) -> Option<(P, K, V)> {
+        self.map.pop_min_full()
+    }
+
+    /// Consumes the map and returns all entries in pop order.
+    pub fn into_sorted_vec(self) -> Vec<(P, K, V)> {
+        self.map.into_sorted_vec()
+    }
+
+    /// Returns the underlying map, which no longer limits its length.
+    pub fn into_inner(self) -> PriorityMap<P, K, V, S, O, B> {
+        self.map
+    }
+}
+
+/// Inserts all entries and passes the ones that do not fit, and the old entries of
+/// existing keys, to the [`Evict`] hook.
+impl<P, K, V, S, O, B, E> Extend<(P, K, V)> for BoundedPriorityMap<P, K, V, S, O, B, E>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+    B: DoubleEndedBackend<P>,
+    E: Evict<P, K, V>,
+{
+    fn extend<I: IntoIterator<Item = (P, K, V)>>(&mut self, iter: I) {
+        for (priority, key, value) in iter {
+            match self.insert(priority, key, value) {
+                Ok(None) => {}
+                Ok(Some((priority, key, value))) | Err(CapacityError((priority, key, value))) => {
+                    self.evict.evict(priority, key, value);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BoundedPriorityMap, CapacityError};
+    use crate::Min;
+
+    #[test]
+    fn keeps_top_entries() {
+        let mut map = BoundedPriorityMap::new(3);
+        for (priority, key) in [(5, "e"), (1, "a"), (4, "d"), (2, "b")] {
+            map.insert(priority, key, ()).unwrap();
+        }
+        assert!(map.is_full());
+        assert_eq!(map.peek_min(), Some((&2, &"b", &())));
+        assert_eq!(map.insert(1, "z", ()), Err(CapacityError((1, "z", ()))));
+        assert_eq!(map.insert(3, "c", ()), Ok(Some((2, "b", ()))));
+        assert_eq!(map.insert(0, "e", ()), Ok(Some((5, "e", ()))));
+        assert_eq!(map.len(), 3);
+        assert_eq!(
+            map.into_sorted_vec(),
+            [(4, "d", ()), (3, "c", ()), (0, "e", ())]
+        );
+    }
+
+    #[test]
+    fn min_order() {
+        let mut map = BoundedPriorityMap::with_order(2, Min);
+        let evicted: Vec<_> = (0..6)
+            .rev()
+            .filter_map(|i| map.insert(i, i, ()).unwrap())
+            .map(|(priority, _, _)| priority)
+            .collect();
+        assert_eq!(evicted, [5, 4, 3, 2]);
+        assert!(!map.admits(&1));
+        assert!(map.admits(&-1));
+        assert_eq!(map.pop(), Some((0, 0, ())));
+
+        let mut map = BoundedPriorityMap::new(0);
+        assert!(map.insert(1, 1, ()).is_err());
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn evict_hook() {
+        let mut evicted = vec![];
+        let mut map = BoundedPriorityMap::new(3).on_evict(|priority, key, ()| {
+            evicted.push((priority, key));
+        });
+        map.extend(
+            [(1, "a"), (5, "b"), (3, "c"), (4, "d"), (0, "e"), (6, "c")]
+                .map(|(priority, key)| (priority, key, ())),
+        );
+        map.set_max_len(1);
+        assert_eq!(map.into_sorted_vec(), [(6, "c", ())]);
+        assert_eq!(evicted, [(1, "a"), (0, "e"), (3, "c"), (4, "d"), (5, "b")]);
+    }
+
+    #[test]
+    fn insert_skips_evict_hook() {
+        let mut evicted = vec![];
+        let mut map = BoundedPriorityMap::new(2).on_evict(|_, key, ()| evicted.push(key));
+        assert_eq!(map.insert(1, "a", ()), Ok(None));
+        assert_eq!(map.insert(2, "b", ()), Ok(None));
+        assert_eq!(map.insert(3, "b", ()), Ok(Some((2, "b", ()))));
+        assert_eq!(map.insert(4, "c", ()), Ok(Some((1, "a", ()))));
+        assert_eq!(map.insert(0, "d", ()), Err(CapacityError((0, "d", ()))));
+        map.extend([(5, "e", ())]);
+        drop(map);
+        assert_eq!(evicted, ["b"]);
+    }
+}
@@ -0,0 +1,279 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+use crate::backend::{DoubleEndedBackend, MinMaxHeap};
+use crate::evict::{Discard, Evict};
+use crate::order::{Max, PriorityOrder};
+use crate::PriorityMap;
+
+/// Estimates the memory taken by an entry, in bytes.
+///
+/// Implemented for [`SizeOf`] and any `Fn(&K, &V) -> usize` closure. The estimate of an
+/// entry must not change while it is in the map.
+pub trait EntrySize<K, V> {
+    fn entry_size(&self, key: &K, value: &V) -> usize;
+}
+
+/// Estimates every entry as the inline size of its key and value, ignoring any heap
+/// allocations they own. This is the default.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SizeOf;
+
+impl<K, V> EntrySize<K, V> for SizeOf {
+    fn entry_size(&self, _key: &K, _value: &V) -> usize {
+        std::mem::size_of::<(K, V)>()
+    }
+}
+
+impl<K, V, F> EntrySize<K, V> for F
+where
+    F: Fn(&K, &V) -> usize,
+{
+    fn entry_size(&self, key: &K, value: &V) -> usize {
+        self(key, value)
+    }
+}
+
+/// A [`PriorityMap`] that caps the estimated memory of its entries instead of their number.
+///
+/// The size of every entry is estimated by `Z`, see [`EntrySize`]. After each insert, the
+/// entries that pop last are evicted until the total fits the budget again, which may
+/// evict the new entry itself. Evicted entries go to the [`Evict`] hook `E`.
+pub struct BudgetPriorityMap<
+    P,
+    K,
+    V,
+    Z = SizeOf,
+    S = RandomState,
+    O = Max,
+    B = MinMaxHeap<P>,
+    E = Discard,
+> where
+    K: Hash,
+{
+    map: PriorityMap<P, K, V, S, O, B>,
+    budget: usize,
+    /// The total estimated size of all entries.
+    used: usize,
+    size: Z,
+    evict: E,
+}
+
+impl<P, K, V> BudgetPriorityMap<P, K, V>
+where
+    P: PartialOrd,
+    K: Eq + Hash,
+{
+    pub fn new(budget: usize) -> Self {
+        Self::with_order(budget, Max)
+    }
+}
+
+impl<P, K, V, O> BudgetPriorityMap<P, K, V, SizeOf, RandomState, O>
+where
+    K: Eq + Hash,
+    O: PriorityOrder<P>,
+{
+    pub fn with_order(budget: usize, order: O) -> Self {
+        Self {
+            map: PriorityMap::with_order(order).into_backend(),
+            budget,
+            used: 0,
+            size: SizeOf,
+            evict: Discard,
+        }
+    }
+}
+
+impl<P, K, V, Z, S, O, B, E> BudgetPriorityMap<P, K, V, Z, S, O, B, E>
+where
+    K: Eq + Hash,
+    Z: EntrySize<K, V>,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+    B: DoubleEndedBackend<P>,
+    E: Evict<P, K, V>,
+{
+    /// Replaces the estimate of entry sizes and evicts entries until the map fits the
+    /// budget under the new estimate.
+    pub fn size_by<F>(self, size: F) -> BudgetPriorityMap<P, K, V, F, S, O, B, E>
+    where
+        F: EntrySize<K, V>,
+    {
+        let used = self
+            .map
+            .iter()
+            .map(|(_, key, value)| size.entry_size(key, value))
+            .sum();
+        let mut map = BudgetPriorityMap {
+            map: self.map,
+            budget: self.budget,
+            used,
+            size,
+            evict: self.evict,
+        };
+        map.evict_over_budget();
+        map
+    }
+
+    /// Replaces the hook that receives evicted entries.
+    pub fn on_evict<F>(self, evict: F) -> BudgetPriorityMap<P, K, V, Z, S, O, B, F>
+    where
+        F: Evict<P, K, V>,
+    {
+        BudgetPriorityMap {
+            map: self.map,
+            budget: self.budget,
+            used: self.used,
+            size: self.size,
+            evict,
+        }
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Changes the budget, evicting the entries that pop last until the map fits.
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.evict_over_budget();
+    }
+
+    /// Returns the total estimated size of all entries.
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Inserts an entry, replacing the priority and value of an existing entry with the
+    /// same key, then evicts entries until the map fits the budget.
+    ///
+    /// Returns the previous priority and value.
+    pub fn insert(&mut self, priority: P, key: K, value: V) -> Option<(P, V)> {
+        if let Some(old) = self.map.get(&key) {
+            self.used -= self.size.entry_size(&key, old);
+        }
+        self.used += self.size.entry_size(&key, &value);
+        let old = self.map.insert(priority, key, value);
+        self.evict_over_budget();
+        old
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<(P, V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let (_, key_ref, value) = self.map.get_full(key)?;
+        self.used -= self.size.entry_size(key_ref, value);
+        self.map.remove_full(key)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.map.contains_key(key)
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.map.get(key)
+    }
+
+    pub fn get_priority<Q>(&self, key: &Q) -> Option<&P>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.map.get_priority(key)
+    }
+
+    pub fn peek(&self) -> Option<(&P, &K, &V)> {
+        self.map.peek_full()
+    }
+
+    /// Returns the entry that pops last, which is evicted next.
+    pub fn peek_min(&self) -> Option<(&P, &K, &V)> {
+        self.map.peek_min_full()
+    }
+
+    pub fn pop(&mut self) -> Option<(P, K, V)> {
+        let (priority, key, value) = self.map.pop_full()?;
+        self.used -= self.size.entry_size(&key, &value);
+        Some((priority, key, value))
+    }
+
+    pub fn pop_min(&mut self) -> Option<(P, K, V)> {
+        let (priority, key, value) = self.map.pop_min_full()?;
+        self.used -= self.size.entry_size(&key, &value);
+        Some((priority, key, value))
+    }
+
+    /// Returns the underlying map, which no longer limits its size.
+    pub fn into_inner(self) -> PriorityMap<P, K, V, S, O, B> {
+        self.map
+    }
+
+    fn evict_over_budget(&mut self) {
+        while self.used > self.budget {
+            let (priority, key, value) = self.pop_min().expect("used size implies entries");
+            self.evict.evict(priority, key, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BudgetPriorityMap;
+
+    #[test]
+    fn evicts_over_budget() {
+        let mut evicted = vec![];
+        let mut map = BudgetPriorityMap::new(10)
+            .size_by(|_: &&str, value: &String| value.len())
+            .on_evict(|priority, key, _| evicted.push((priority, key)));
+        map.insert(3, "a", "xxxx".to_string());
+        map.insert(1, "b", "xxxx".to_string());
+        assert_eq!(map.used(), 8);
+        map.insert(2, "c", "xxxx".to_string());
+        assert_eq!(map.used(), 8);
+        // A large entry that pops last evicts only itself.
+        map.insert(0, "d", "x".repeat(20));
+        assert_eq!(
+            map.insert(5, "c", "x".to_string()),
+            Some((2, "xxxx".to_string()))
+        );
+        assert_eq!(map.used(), 5);
+        assert_eq!(map.remove("a"), Some((3, "xxxx".to_string())));
+        assert_eq!(map.used(), 1);
+        map.set_budget(0);
+        assert!(map.is_empty());
+        assert_eq!(map.used(), 0);
+        drop(map);
+        assert_eq!(evicted, [(1, "b"), (0, "d"), (5, "c")]);
+    }
+
+    #[test]
+    fn size_of() {
+        let mut map = BudgetPriorityMap::new(3 * std::mem::size_of::<(u64, u64)>());
+        for i in 0..10u64 {
+            map.insert(i, i, i);
+        }
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.peek_min(), Some((&7, &7, &7)));
+    }
+}
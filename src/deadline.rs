@@ -0,0 +1,149 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::time::Instant;
+
+use crate::order::Min;
+use crate::PriorityMap;
+
+/// A queue of keyed entries that become ready at a deadline.
+///
+/// Entries pop by earliest deadline, and entries with the same deadline in insertion order.
+/// [`pop_ready`](Self::pop_ready) only pops entries whose deadline has passed, and
+/// [`next_deadline`](Self::next_deadline) tells when to wake up for the next one.
+pub struct DeadlineQueue<K, V, S = RandomState>
+where
+    K: Hash,
+{
+    map: PriorityMap<Instant, K, V, S, Min>,
+}
+
+impl<K, V> DeadlineQueue<K, V>
+where
+    K: Eq + Hash,
+{
+    pub fn new() -> Self {
+        let mut map = PriorityMap::new_min();
+        map.set_stable(true);
+        Self { map }
+    }
+}
+
+impl<K, V> Default for DeadlineQueue<K, V>
+where
+    K: Eq + Hash,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, S> DeadlineQueue<K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Inserts an entry, replacing the deadline and value of an existing entry with the
+    /// same key.
+    ///
+    /// Returns the previous deadline and value.
+    pub fn insert(&mut self, deadline: Instant, key: K, value: V) -> Option<(Instant, V)> {
+        self.map.insert(deadline, key, value)
+    }
+
+    /// Moves the deadline of an existing entry and returns the old deadline.
+    pub fn reschedule<Q>(&mut self, key: &Q, deadline: Instant) -> Option<Instant>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.map.reprioritize(key, deadline)
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<(Instant, V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.map.remove_full(key)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.map.contains_key(key)
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.map.get(key)
+    }
+
+    pub fn deadline<Q>(&self, key: &Q) -> Option<Instant>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.map.get_priority(key).copied()
+    }
+
+    /// Returns the earliest deadline, ready or not.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let (deadline, _, _) = self.map.peek_full()?;
+        Some(*deadline)
+    }
+
+    /// Pops the entry with the earliest deadline if the deadline is at or before `now`.
+    pub fn pop_ready(&mut self, now: Instant) -> Option<(Instant, K, V)> {
+        self.map.pop_if(|deadline, _, _| *deadline <= now)
+    }
+
+    /// Returns the underlying map.
+    pub fn into_inner(self) -> PriorityMap<Instant, K, V, S, Min> {
+        self.map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::DeadlineQueue;
+
+    #[test]
+    fn pop_ready() {
+        let start = Instant::now();
+        let secs = |secs| start + Duration::from_secs(secs);
+        let mut queue = DeadlineQueue::new();
+        assert_eq!(queue.next_deadline(), None);
+        queue.insert(secs(3), "c", ());
+        queue.insert(secs(1), "a", ());
+        queue.insert(secs(1), "b", ());
+        queue.insert(secs(5), "d", ());
+        assert_eq!(queue.next_deadline(), Some(secs(1)));
+
+        assert_eq!(queue.pop_ready(start), None);
+        assert_eq!(queue.pop_ready(secs(2)), Some((secs(1), "a", ())));
+        assert_eq!(queue.pop_ready(secs(2)), Some((secs(1), "b", ())));
+        assert_eq!(queue.pop_ready(secs(2)), None);
+
+        assert_eq!(queue.reschedule("d", secs(2)), Some(secs(5)));
+        assert_eq!(queue.next_deadline(), Some(secs(2)));
+        assert_eq!(queue.pop_ready(secs(3)), Some((secs(2), "d", ())));
+        assert_eq!(queue.pop_ready(secs(3)), Some((secs(3), "c", ())));
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,220 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::time::{Duration, Instant};
+
+use crate::order::{Max, PriorityOrder};
+use crate::PriorityMap;
+
+/// Computes how a priority shrinks over time.
+///
+/// The policy must decay all priorities alike, so that whichever of two entries ranks
+/// higher does not change as time passes, like [`HalfLife`] or subtracting a fixed rate.
+/// Implemented for [`HalfLife`] and any `Fn(&P, Duration) -> P` closure.
+pub trait DecayPolicy<P> {
+    /// Returns `priority` decayed for `elapsed`.
+    fn decay(&self, priority: &P, elapsed: Duration) -> P;
+}
+
+impl<P, F> DecayPolicy<P> for F
+where
+    F: Fn(&P, Duration) -> P,
+{
+    fn decay(&self, priority: &P, elapsed: Duration) -> P {
+        self(priority, elapsed)
+    }
+}
+
+/// Halves an `f64` priority every given duration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HalfLife(pub Duration);
+
+impl DecayPolicy<f64> for HalfLife {
+    fn decay(&self, priority: &f64, elapsed: Duration) -> f64 {
+        priority * 0.5f64.powf(elapsed.as_secs_f64() / self.0.as_secs_f64())
+    }
+}
+
+/// Compares priorities with their insertion time by decaying both to the later one.
+struct Decay<D, O> {
+    policy: D,
+    order: O,
+}
+
+impl<P, D, O> PriorityOrder<(P, Instant)> for Decay<D, O>
+where
+    D: DecayPolicy<P>,
+    O: PriorityOrder<P>,
+{
+    fn higher(&self, a: &(P, Instant), b: &(P, Instant)) -> bool {
+        let at = a.1.max(b.1);
+        let a = self.policy.decay(&a.0, at - a.1);
+        let b = self.policy.decay(&b.0, at - b.1);
+        self.order.higher(&a, &b)
+    }
+}
+
+/// A [`PriorityMap`] whose priorities decay over time.
+///
+/// Every entry stores the time it was inserted. Comparisons decay both priorities to the
+/// same instant with the [`DecayPolicy`] `D`, so the heap stays ordered as time passes
+/// without rewriting any priority.
+pub struct DecayingPriorityMap<P, K, V, D, S = RandomState, O = Max>
+where
+    K: Hash,
+{
+    map: PriorityMap<(P, Instant), K, V, S, Decay<D, O>>,
+}
+
+impl<P, K, V, D> DecayingPriorityMap<P, K, V, D>
+where
+    P: PartialOrd,
+    K: Eq + Hash,
+    D: DecayPolicy<P>,
+{
+    pub fn new(policy: D) -> Self {
+        Self::with_order(policy, Max)
+    }
+}
+
+impl<P, K, V, D, O> DecayingPriorityMap<P, K, V, D, RandomState, O>
+where
+    K: Eq + Hash,
+    D: DecayPolicy<P>,
+    O: PriorityOrder<P>,
+{
+    pub fn with_order(policy: D, order: O) -> Self {
+        Self {
+            map: PriorityMap::with_order(Decay { policy, order }),
+        }
+    }
+}
+
+impl<P, K, V, D, S, O> DecayingPriorityMap<P, K, V, D, S, O>
+where
+    K: Eq + Hash,
+    D: DecayPolicy<P>,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+{
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Inserts an entry whose priority starts decaying now, replacing the priority and
+    /// value of an existing entry with the same key.
+    ///
+    /// Returns the previous priority, as inserted, and value.
+    pub fn insert(&mut self, priority: P, key: K, value: V) -> Option<(P, V)> {
+        self.insert_at(priority, key, value, Instant::now())
+    }
+
+    /// Inserts an entry whose priority started decaying at `since`, replacing the priority
+    /// and value of an existing entry with the same key.
+    ///
+    /// Returns the previous priority, as inserted, and value.
+    pub fn insert_at(&mut self, priority: P, key: K, value: V, since: Instant) -> Option<(P, V)> {
+        let ((priority, _), value) = self.map.insert((priority, since), key, value)?;
+        Some((priority, value))
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<(P, V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let ((priority, _), value) = self.map.remove_full(key)?;
+        Some((priority, value))
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.map.contains_key(key)
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.map.get(key)
+    }
+
+    /// Returns the priority the entry was inserted with.
+    pub fn get_priority<Q>(&self, key: &Q) -> Option<&P>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        Some(&self.map.get_priority(key)?.0)
+    }
+
+    /// Returns the priority of the entry decayed until `now`.
+    pub fn priority_at<Q>(&self, key: &Q, now: Instant) -> Option<P>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let (priority, since) = self.map.get_priority(key)?;
+        Some(self.decay(priority, *since, now))
+    }
+
+    /// Returns the top entry with its priority decayed until `now`.
+    pub fn peek(&self, now: Instant) -> Option<(P, &K, &V)> {
+        let ((priority, since), key, value) = self.map.peek_full()?;
+        Some((self.decay(priority, *since, now), key, value))
+    }
+
+    /// Pops the top entry and returns it with its priority as inserted.
+    pub fn pop(&mut self) -> Option<(P, K, V)> {
+        let ((priority, _), key, value) = self.map.pop_full()?;
+        Some((priority, key, value))
+    }
+
+    fn decay(&self, priority: &P, since: Instant, now: Instant) -> P {
+        let elapsed = now.saturating_duration_since(since);
+        self.map.order().policy.decay(priority, elapsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{DecayingPriorityMap, HalfLife};
+    use crate::Min;
+
+    #[test]
+    fn half_life() {
+        let start = Instant::now();
+        let secs = |secs| start + Duration::from_secs(secs);
+        let mut map = DecayingPriorityMap::new(HalfLife(Duration::from_secs(10)));
+        map.insert_at(8.0, "old", (), start);
+        map.insert_at(5.0, "new", (), secs(10));
+        map.insert_at(3.0, "newer", (), secs(20));
+        assert_eq!(map.priority_at("old", secs(20)), Some(2.0));
+        assert_eq!(map.peek(secs(30)), Some((1.5, &"newer", &())));
+        assert_eq!(map.pop(), Some((3.0, "newer", ())));
+        assert_eq!(map.pop(), Some((5.0, "new", ())));
+        assert_eq!(map.pop(), Some((8.0, "old", ())));
+    }
+
+    #[test]
+    fn closure_policy() {
+        let decay = |priority: &i64, elapsed: Duration| priority - elapsed.as_secs() as i64;
+        let start = Instant::now();
+        let mut map = DecayingPriorityMap::with_order(decay, Min);
+        map.insert_at(5, "a", (), start);
+        map.insert_at(3, "b", (), start + Duration::from_secs(5));
+        // Decayed to the same instant, "a" is 0 and "b" is 3.
+        assert_eq!(map.pop(), Some((5, "a", ())));
+        assert_eq!(map.get_priority("b"), Some(&3));
+    }
+}
@@ -0,0 +1,298 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::time::{Duration, Instant};
+
+use crate::DeadlineQueue;
+
+/// A map of values that become available after a delay, addressed by key.
+///
+/// Like the `DelayQueue` of `tokio-util`, but synchronous: the caller polls with
+/// [`poll_expired`](Self::poll_expired), and programs a timer with
+/// [`next_deadline`](Self::next_deadline). Built on a [`DeadlineQueue`].
+///
+/// Recurring entries from [`insert_every`](Self::insert_every) expire once per period.
+pub struct DelayMap<K, V, S = RandomState>
+where
+    K: Hash,
+{
+    queue: DeadlineQueue<K, Delayed<K, V>, S>,
+}
+
+struct Delayed<K, V> {
+    value: V,
+    recur: Option<Recur<K, V>>,
+}
+
+/// How a recurring entry re-arms itself.
+struct Recur<K, V> {
+    period: Duration,
+    /// Clones the entry for the next period, so that only recurring entries need `Clone`.
+    clone: fn(&K, &V) -> (K, V),
+}
+
+impl<K, V> Clone for Recur<K, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K, V> Copy for Recur<K, V> {}
+
+impl<K, V> DelayMap<K, V>
+where
+    K: Eq + Hash,
+{
+    pub fn new() -> Self {
+        Self {
+            queue: DeadlineQueue::new(),
+        }
+    }
+}
+
+impl<K, V> Default for DelayMap<K, V>
+where
+    K: Eq + Hash,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, S> DelayMap<K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Schedules `value` to expire `delay` from now, replacing the value and deadline of
+    /// an existing entry with the same key.
+    ///
+    /// Returns the previous value.
+    pub fn insert(&mut self, key: K, value: V, delay: Duration) -> Option<V> {
+        self.insert_at(key, value, Instant::now() + delay)
+    }
+
+    /// Schedules `value` to expire at `deadline`, replacing the value and deadline of an
+    /// existing entry with the same key.
+    ///
+    /// Returns the previous value.
+    pub fn insert_at(&mut self, key: K, value: V, deadline: Instant) -> Option<V> {
+        self.insert_delayed(key, Delayed { value, recur: None }, deadline)
+    }
+
+    /// Schedules `value` to expire every `period`, starting one period from now, replacing
+    /// the value and deadline of an existing entry with the same key.
+    ///
+    /// Returns the previous value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `period` is zero.
+    pub fn insert_every(&mut self, key: K, value: V, period: Duration) -> Option<V>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        self.insert_every_at(key, value, Instant::now() + period, period)
+    }
+
+    /// Schedules `value` to expire at `first` and then every `period`, replacing the value
+    /// and deadline of an existing entry with the same key.
+    ///
+    /// Returns the previous value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `period` is zero.
+    pub fn insert_every_at(
+        &mut self,
+        key: K,
+        value: V,
+        first: Instant,
+        period: Duration,
+    ) -> Option<V>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        assert!(!period.is_zero(), "period must not be zero");
+        let recur = Recur {
+            period,
+            clone: |key: &K, value: &V| (key.clone(), value.clone()),
+        };
+        let delayed = Delayed {
+            value,
+            recur: Some(recur),
+        };
+        self.insert_delayed(key, delayed, first)
+    }
+
+    fn insert_delayed(&mut self, key: K, delayed: Delayed<K, V>, deadline: Instant) -> Option<V> {
+        let (_, delayed) = self.queue.insert(deadline, key, delayed)?;
+        Some(delayed.value)
+    }
+
+    /// Moves the expiry of an existing entry to `delay` from now and returns whether the
+    /// key exists.
+    pub fn reset<Q>(&mut self, key: &Q, delay: Duration) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.reset_at(key, Instant::now() + delay)
+    }
+
+    /// Moves the expiry of an existing entry to `deadline` and returns whether the key
+    /// exists.
+    pub fn reset_at<Q>(&mut self, key: &Q, deadline: Instant) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.queue.reschedule(key, deadline).is_some()
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let (_, delayed) = self.queue.remove(key)?;
+        Some(delayed.value)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.queue.contains_key(key)
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        Some(&self.queue.get(key)?.value)
+    }
+
+    /// Returns the period of a recurring entry.
+    pub fn period<Q>(&self, key: &Q) -> Option<Duration>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        Some(self.queue.get(key)?.recur?.period)
+    }
+
+    /// Returns when the entry expires.
+    pub fn deadline<Q>(&self, key: &Q) -> Option<Instant>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.queue.deadline(key)
+    }
+
+    /// Returns when the next entry expires.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.queue.next_deadline()
+    }
+
+    /// Removes and returns the entry that expired first, if any expired at `now`.
+    ///
+    /// A recurring entry stays in the map with a clone of its value, due one period after
+    /// the deadline that expired. Periods that already passed by `now` are skipped rather
+    /// than expiring in a burst.
+    pub fn poll_expired(&mut self, now: Instant) -> Option<(K, V)> {
+        let (deadline, key, delayed) = self.queue.pop_ready(now)?;
+        if let Some(recur) = delayed.recur {
+            let (next_key, value) = (recur.clone)(&key, &delayed.value);
+            let mut next = deadline + recur.period;
+            if next <= now {
+                // The time since the last missed deadline is below one period, which fits.
+                let behind = (now - next).as_nanos() % recur.period.as_nanos();
+                next = now + (recur.period - Duration::from_nanos(behind as u64));
+            }
+            let delayed = Delayed {
+                value,
+                recur: Some(recur),
+            };
+            self.queue.insert(next, next_key, delayed);
+        }
+        Some((key, delayed.value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::DelayMap;
+
+    #[test]
+    fn poll_expired() {
+        let start = Instant::now();
+        let mut map = DelayMap::new();
+        map.insert("now", 0, Duration::ZERO);
+        map.insert_at("later", 1, start + Duration::from_secs(60));
+        map.insert("never", 2, Duration::from_secs(3600));
+        assert_eq!(map.poll_expired(Instant::now()), Some(("now", 0)));
+        assert_eq!(map.poll_expired(Instant::now()), None);
+
+        assert!(map.reset_at("never", start + Duration::from_secs(30)));
+        assert!(!map.reset_at("missing", start));
+        assert_eq!(map.next_deadline(), Some(start + Duration::from_secs(30)));
+        assert_eq!(map.insert_at("later", 3, start), Some(1));
+        let now = start + Duration::from_secs(40);
+        assert_eq!(map.poll_expired(now), Some(("later", 3)));
+        assert_eq!(map.poll_expired(now), Some(("never", 2)));
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn recurring() {
+        let start = Instant::now();
+        let secs = |secs| start + Duration::from_secs(secs);
+        let mut map = DelayMap::new();
+        map.insert_every_at("tick", 0, secs(10), Duration::from_secs(10));
+        map.insert_at("once", 1, secs(15));
+        assert_eq!(map.period("tick"), Some(Duration::from_secs(10)));
+        assert_eq!(map.period("once"), None);
+
+        assert_eq!(map.poll_expired(secs(10)), Some(("tick", 0)));
+        assert_eq!(map.deadline("tick"), Some(secs(20)));
+        assert_eq!(map.poll_expired(secs(10)), None);
+        assert_eq!(map.poll_expired(secs(20)), Some(("once", 1)));
+        assert_eq!(map.poll_expired(secs(20)), Some(("tick", 0)));
+
+        // Missed periods are skipped.
+        assert_eq!(map.poll_expired(secs(55)), Some(("tick", 0)));
+        assert_eq!(map.deadline("tick"), Some(secs(60)));
+        assert_eq!(map.poll_expired(secs(55)), None);
+
+        // Even many missed periods are skipped at once.
+        let micro = Duration::from_micros(1);
+        map.insert_every_at("fast", 3, secs(55), micro);
+        let later = secs(55) + Duration::from_secs(3 * 3600) + micro / 2;
+        assert_eq!(map.poll_expired(later), Some(("fast", 3)));
+        assert_eq!(map.deadline("fast"), Some(later + micro / 2));
+        assert_eq!(map.poll_expired(later), Some(("tick", 0)));
+        assert_eq!(map.poll_expired(later), None);
+        assert_eq!(map.remove("fast"), Some(3));
+
+        // Inserting a one-shot value ends the recurrence.
+        map.insert_at("tick", 2, secs(60));
+        assert_eq!(map.poll_expired(secs(60)), Some(("tick", 2)));
+        assert!(map.is_empty());
+    }
+}
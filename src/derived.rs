@@ -0,0 +1,194 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+use crate::order::{Max, PriorityOrder};
+use crate::PriorityMap;
+
+/// Computes the priority of an entry from its value.
+///
+/// Implemented for any `Fn(&V) -> P` closure.
+pub trait PriorityFrom<V> {
+    type Priority;
+
+    fn priority_from(&self, value: &V) -> Self::Priority;
+}
+
+impl<V, P, F> PriorityFrom<V> for F
+where
+    F: Fn(&V) -> P,
+{
+    type Priority = P;
+
+    fn priority_from(&self, value: &V) -> P {
+        self(value)
+    }
+}
+
+/// A [`PriorityMap`] whose priorities are derived from the values.
+///
+/// The priority is recomputed whenever a value is inserted or changed through
+/// [`update_value`](Self::update_value), so it can never go stale.
+pub struct DerivedPriorityMap<K, V, F, S = RandomState, O = Max>
+where
+    K: Hash,
+    F: PriorityFrom<V>,
+{
+    map: PriorityMap<F::Priority, K, V, S, O>,
+    priority: F,
+}
+
+impl<K, V, F> DerivedPriorityMap<K, V, F>
+where
+    K: Eq + Hash,
+    F: PriorityFrom<V>,
+    F::Priority: PartialOrd,
+{
+    /// Creates an empty map that computes priorities with `priority`.
+    pub fn new(priority: F) -> Self {
+        Self {
+            map: PriorityMap::new(),
+            priority,
+        }
+    }
+}
+
+impl<K, V, F, S, O> DerivedPriorityMap<K, V, F, S, O>
+where
+    K: Eq + Hash,
+    F: PriorityFrom<V>,
+    S: BuildHasher,
+    O: PriorityOrder<F::Priority>,
+{
+    /// Wraps an empty `map`, which decides the order and hasher.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `map` is not empty.
+    pub fn with_map(map: PriorityMap<F::Priority, K, V, S, O>, priority: F) -> Self {
+        assert!(map.is_empty(), "map must be empty");
+        Self { map, priority }
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Inserts a value under `key`, returning the previous value if there was one.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let priority = self.priority.priority_from(&value);
+        self.map
+            .insert(priority, key, value)
+            .map(|(_, value)| value)
+    }
+
+    /// Changes the value of an existing entry with `f` and recomputes its priority.
+    ///
+    /// Returns `false` if the key does not exist.
+    pub fn update_value<Q, U>(&mut self, key: &Q, f: U) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+        U: FnOnce(&mut V),
+    {
+        let Some(value) = self.map.get_mut(key) else {
+            return false;
+        };
+        f(value);
+        let priority = self.priority.priority_from(value);
+        self.map.reprioritize(key, priority);
+        true
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.map.contains_key(key)
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.map.get(key)
+    }
+
+    pub fn peek(&self) -> Option<(&K, &V)> {
+        self.map.peek_full().map(|(_, key, value)| (key, value))
+    }
+
+    pub fn pop(&mut self) -> Option<(K, V)> {
+        self.map.pop_full().map(|(_, key, value)| (key, value))
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.map.remove(key)
+    }
+
+    /// Returns the underlying map for read-only access to the rest of its API.
+    pub fn as_map(&self) -> &PriorityMap<F::Priority, K, V, S, O> {
+        &self.map
+    }
+
+    pub fn into_map(self) -> PriorityMap<F::Priority, K, V, S, O> {
+        self.map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DerivedPriorityMap;
+
+    #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct Job {
+        retries: u32,
+        name: &'static str,
+    }
+
+    #[test]
+    fn derived_priority() {
+        let mut map = DerivedPriorityMap::new(|job: &Job| job.retries);
+        map.insert(
+            1,
+            Job {
+                retries: 2,
+                name: "a",
+            },
+        );
+        map.insert(
+            2,
+            Job {
+                retries: 5,
+                name: "b",
+            },
+        );
+        map.insert(
+            3,
+            Job {
+                retries: 3,
+                name: "c",
+            },
+        );
+        assert_eq!(map.peek().map(|(key, _)| *key), Some(2));
+
+        assert!(map.update_value(&1, |job| job.retries = 9));
+        assert!(!map.update_value(&4, |job| job.retries = 9));
+        assert_eq!(map.as_map().get_priority(&1), Some(&9));
+
+        assert_eq!(map.pop().map(|(_, job)| job.name), Some("a"));
+        assert_eq!(map.remove(&3).map(|job| job.name), Some("c"));
+        assert_eq!(map.pop().map(|(_, job)| job.name), Some("b"));
+        assert!(map.is_empty());
+    }
+}
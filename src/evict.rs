@@ -0,0 +1,24 @@
+/// Receives the entries that a map displaces without returning them, so that resources
+/// held by their values can be released deterministically.
+///
+/// Implemented for [`Discard`] and any `FnMut(P, K, V)` closure.
+pub trait Evict<P, K, V> {
+    fn evict(&mut self, priority: P, key: K, value: V);
+}
+
+/// Drops displaced entries. This is the default.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Discard;
+
+impl<P, K, V> Evict<P, K, V> for Discard {
+    fn evict(&mut self, _priority: P, _key: K, _value: V) {}
+}
+
+impl<P, K, V, F> Evict<P, K, V> for F
+where
+    F: FnMut(P, K, V),
+{
+    fn evict(&mut self, priority: P, key: K, value: V) {
+        self(priority, key, value);
+    }
+}
@@ -0,0 +1,230 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+use crate::order::{Max, PriorityOrder};
+use crate::PriorityMap;
+
+/// A [`PriorityMap`] that removes entries lazily.
+///
+/// [`cancel`](Self::cancel) only marks the entry as dead, in `O(1)` unless it is the top
+/// entry. Dead entries are skipped when popping, and purged in a single `O(n)` pass once
+/// they outnumber the live ones.
+pub struct LazyPriorityMap<P, K, V, S = RandomState, O = Max>
+where
+    K: Hash,
+{
+    map: PriorityMap<P, K, Option<V>, S, O>,
+    dead: usize,
+}
+
+impl<P, K, V> LazyPriorityMap<P, K, V>
+where
+    P: PartialOrd,
+    K: Eq + Hash,
+{
+    pub fn new() -> Self {
+        Self::with_order(Max)
+    }
+}
+
+impl<P, K, V> Default for LazyPriorityMap<P, K, V>
+where
+    P: PartialOrd,
+    K: Eq + Hash,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P, K, V, O> LazyPriorityMap<P, K, V, RandomState, O>
+where
+    K: Eq + Hash,
+    O: PriorityOrder<P>,
+{
+    pub fn with_order(order: O) -> Self {
+        Self::with_order_and_hasher(order, RandomState::new())
+    }
+}
+
+impl<P, K, V, S, O> LazyPriorityMap<P, K, V, S, O>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+{
+    /// Creates an empty map that pops entries according to `order` and uses `hasher`
+    /// to hash keys.
+    pub fn with_order_and_hasher(order: O, hasher: S) -> Self {
+        Self {
+            map: PriorityMap::with_order_and_hasher(order, hasher),
+            dead: 0,
+        }
+    }
+
+    /// Returns the number of live entries.
+    pub fn len(&self) -> usize {
+        self.map.len() - self.dead
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of cancelled entries that still take up space.
+    pub fn dead_len(&self) -> usize {
+        self.dead
+    }
+
+    /// Inserts an entry, replacing the priority and value of an existing entry with the
+    /// same key.
+    ///
+    /// Returns the previous priority and value if the key was live.
+    pub fn insert(&mut self, priority: P, key: K, value: V) -> Option<(P, V)> {
+        let (priority, value) = self.map.insert(priority, key, Some(value))?;
+        match value {
+            Some(value) => {
+                // Lowering the top entry may expose a dead one.
+                self.purge_top();
+                Some((priority, value))
+            }
+            None => {
+                self.dead -= 1;
+                None
+            }
+        }
+    }
+
+    /// Marks the entry as dead and returns its value.
+    pub fn cancel<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let value = self.map.get_mut(key)?.take()?;
+        self.dead += 1;
+        self.purge_top();
+        if self.dead > self.map.len() / 2 {
+            self.compact();
+        }
+        Some(value)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.map.get(key)?.as_ref()
+    }
+
+    pub fn get_priority<Q>(&self, key: &Q) -> Option<&P>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.get(key)?;
+        self.map.get_priority(key)
+    }
+
+    /// Changes the priority of a live entry and returns the old priority.
+    pub fn reprioritize<Q>(&mut self, key: &Q, priority: P) -> Option<P>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.get(key)?;
+        let old = self.map.reprioritize(key, priority);
+        self.purge_top();
+        old
+    }
+
+    pub fn peek(&self) -> Option<(&P, &K, &V)> {
+        let (priority, key, value) = self.map.peek_full()?;
+        Some((priority, key, value.as_ref()?))
+    }
+
+    pub fn pop(&mut self) -> Option<(P, K, V)> {
+        let (priority, key, value) = self.map.pop_full()?;
+        self.purge_top();
+        Some((priority, key, value.expect("top entry must be live")))
+    }
+
+    /// Removes all dead entries.
+    pub fn compact(&mut self) {
+        if self.dead > 0 {
+            self.map.retain(|_, _, value| value.is_some());
+            self.dead = 0;
+        }
+    }
+
+    /// Pops dead entries until the top entry is live, so that peeking never sees them.
+    fn purge_top(&mut self) {
+        while self.dead > 0 && self.map.peek().is_some_and(Option::is_none) {
+            self.map.pop();
+            self.dead -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hash_map::RandomState;
+
+    use super::LazyPriorityMap;
+    use crate::Min;
+
+    #[test]
+    fn cancel() {
+        let mut map = LazyPriorityMap::new();
+        for i in 0..10 {
+            map.insert(i, i, i * 10);
+        }
+
+        assert_eq!(map.cancel(&3), Some(30));
+        assert_eq!(map.cancel(&3), None);
+        assert_eq!(map.cancel(&9), Some(90));
+        assert_eq!((map.len(), map.dead_len()), (8, 1));
+        assert!(!map.contains_key(&3));
+        assert_eq!(map.peek(), Some((&8, &8, &80)));
+
+        assert_eq!(map.insert(11, 3, 31), None);
+        assert_eq!(map.dead_len(), 0);
+        map.cancel(&7);
+        assert_eq!(map.insert(5, 8, 81), Some((8, 80)));
+        assert_eq!(map.insert(7, 7, 71), None);
+        for key in [0, 1, 2, 4, 5] {
+            map.cancel(&key);
+        }
+        assert!(map.dead_len() <= map.len());
+        assert!(map.reprioritize(&5, 20).is_none());
+
+        let popped: Vec<_> = std::iter::from_fn(|| map.pop()).collect();
+        assert_eq!(popped, [(11, 3, 31), (7, 7, 71), (6, 6, 60), (5, 8, 81)]);
+        assert_eq!(map.dead_len(), 0);
+    }
+
+    #[test]
+    fn with_order() {
+        let mut map = LazyPriorityMap::with_order_and_hasher(Min, RandomState::new());
+        for i in 0..4 {
+            map.insert(i, i, ());
+        }
+        map.cancel(&0);
+        assert_eq!(map.pop(), Some((1, 1, ())));
+        let mut map = LazyPriorityMap::with_order(|a: &i32, b: &i32| a % 3 > b % 3);
+        for (priority, key) in [(4, "a"), (2, "b"), (6, "c")] {
+            map.insert(priority, key, ());
+        }
+        assert_eq!(map.pop(), Some((2, "b", ())));
+    }
+}
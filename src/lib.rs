@@ -1,3 +1,48 @@
+mod aging;
+mod backend;
+mod bounded;
+mod budget;
+mod deadline;
+mod decay;
+mod delay;
+mod derived;
+mod evict;
+mod lazy;
+mod merge;
+mod monotone;
+mod order;
 mod prioritymap;
+mod storage;
+mod total;
+mod ttl;
 
-pub use prioritymap::PriorityMap;
+pub use aging::{AgingPolicy, AgingPriorityMap};
+#[cfg(feature = "rand")]
+pub use backend::Weighted;
+pub use backend::{
+    BucketPriority, BucketQueue, DAryHeap, DoubleEndedBackend, HeapBackend, MinMaxHeap, PairingHeap,
+};
+pub use bounded::{BoundedPriorityMap, CapacityError};
+pub use budget::{BudgetPriorityMap, EntrySize, SizeOf};
+pub use deadline::DeadlineQueue;
+pub use decay::{DecayPolicy, DecayingPriorityMap, HalfLife};
+pub use delay::DelayMap;
+pub use derived::{DerivedPriorityMap, PriorityFrom};
+pub use evict::{Discard, Evict};
+pub use lazy::LazyPriorityMap;
+pub use merge::{merge_sorted, merge_sorted_by, MergeSorted};
+pub use monotone::{MonotoneError, MonotonePriorityMap};
+pub use order::{Max, Min, PriorityOrder, ThenBy};
+#[cfg(feature = "ahash")]
+pub use prioritymap::AHashPriorityMap;
+#[cfg(feature = "rustc-hash")]
+pub use prioritymap::FxPriorityMap;
+#[cfg(feature = "rand")]
+pub use prioritymap::WeightedPriorityMap;
+pub use prioritymap::{
+    iter, BatchInsert, BoxedPriorityMap, BucketPriorityMap, DoubleEndedPriorityMap, MinPriorityMap,
+    OccupiedError, PairingPriorityMap, PeekMut, PriorityMap, TimestampedPriorityMap,
+};
+pub use storage::{Storage, Timestamped};
+pub use total::TotalF64;
+pub use ttl::TtlPriorityMap;
@@ -0,0 +1,172 @@
+use crate::order::{Max, PriorityOrder};
+
+/// Merges iterators that yield `(priority, key, value)` entries by descending priority
+/// into a single iterator by descending priority.
+///
+/// Works with the sorted iterators of [`PriorityMap`](crate::PriorityMap), for example
+/// [`drain_sorted`](crate::PriorityMap::drain_sorted) or
+/// [`iter_sorted`](crate::PriorityMap::iter_sorted).
+pub fn merge_sorted<I, P, K, V>(iters: impl IntoIterator<Item = I>) -> MergeSorted<I, P, K, V>
+where
+    I: Iterator<Item = (P, K, V)>,
+    P: PartialOrd,
+{
+    merge_sorted_by(iters, Max)
+}
+
+/// Like [`merge_sorted`], but for sources sorted highest-first under `order`,
+/// such as the sorted iterators of a min-first map.
+pub fn merge_sorted_by<I, P, K, V, O>(
+    iters: impl IntoIterator<Item = I>,
+    order: O,
+) -> MergeSorted<I, P, K, V, O>
+where
+    I: Iterator<Item = (P, K, V)>,
+    O: PriorityOrder<P>,
+{
+    let mut merge = MergeSorted {
+        sources: iters.into_iter().collect(),
+        heads: Vec::new(),
+        order,
+    };
+    merge.heads.reserve(merge.sources.len());
+    for source in 0..merge.sources.len() {
+        merge.advance(source);
+    }
+    merge
+}
+
+/// Iterator returned by [`merge_sorted`].
+///
+/// Keeps the next entry of every source in a small heap, so each step costs
+/// `O(log k)` for `k` sources.
+pub struct MergeSorted<I, P, K, V, O = Max> {
+    sources: Vec<I>,
+    heads: Vec<((P, K, V), usize)>,
+    order: O,
+}
+
+impl<I, P, K, V, O> MergeSorted<I, P, K, V, O>
+where
+    I: Iterator<Item = (P, K, V)>,
+    O: PriorityOrder<P>,
+{
+    fn higher(&self, a: usize, b: usize) -> bool {
+        self.order.higher(&self.heads[a].0 .0, &self.heads[b].0 .0)
+    }
+
+    fn advance(&mut self, source: usize) {
+        let Some(item) = self.sources[source].next() else {
+            return;
+        };
+        self.heads.push((item, source));
+        let mut position = self.heads.len() - 1;
+        while position > 0 {
+            let parent = (position - 1) / 2;
+            if !self.higher(position, parent) {
+                break;
+            }
+            self.heads.swap(parent, position);
+            position = parent;
+        }
+    }
+
+    fn pop_head(&mut self) -> Option<((P, K, V), usize)> {
+        if self.heads.is_empty() {
+            return None;
+        }
+        let head = self.heads.swap_remove(0);
+        let mut position = 0;
+        loop {
+            let left = 2 * position + 1;
+            if left >= self.heads.len() {
+                break;
+            }
+            let right = left + 1;
+            let child = if right < self.heads.len() && self.higher(right, left) {
+                right
+            } else {
+                left
+            };
+            if !self.higher(child, position) {
+                break;
+            }
+            self.heads.swap(child, position);
+            position = child;
+        }
+        Some(head)
+    }
+}
+
+impl<I, P, K, V, O> Iterator for MergeSorted<I, P, K, V, O>
+where
+    I: Iterator<Item = (P, K, V)>,
+    O: PriorityOrder<P>,
+{
+    type Item = (P, K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (item, source) = self.pop_head()?;
+        self.advance(source);
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.sources.iter().map(Iterator::size_hint).fold(
+            (self.heads.len(), Some(self.heads.len())),
+            |acc, hint| {
+                (
+                    acc.0.saturating_add(hint.0),
+                    acc.1.zip(hint.1).and_then(|(a, b)| a.checked_add(b)),
+                )
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::PriorityMap;
+
+    #[test]
+    fn merge_sorted() {
+        let mut maps: Vec<_> = (0..3)
+            .map(|shard| {
+                (0..10)
+                    .filter(|i| i % 3 == shard)
+                    .map(|i| (i, i, shard))
+                    .collect::<PriorityMap<_, _, _>>()
+            })
+            .collect();
+
+        let merged: Vec<_> = super::merge_sorted(maps.iter().map(PriorityMap::iter_sorted))
+            .map(|(priority, _, _)| *priority)
+            .collect();
+        assert_eq!(merged, [9, 8, 7, 6, 5, 4, 3, 2, 1, 0]);
+
+        let merge = super::merge_sorted(maps.iter_mut().map(PriorityMap::drain_sorted));
+        assert_eq!(merge.size_hint(), (10, Some(10)));
+        let merged: Vec<_> = merge.take(2).collect();
+        assert_eq!(merged, [(9, 9, 0), (8, 8, 2)]);
+        assert!(maps.iter().all(PriorityMap::is_empty));
+    }
+
+    #[test]
+    fn merge_sorted_by_min() {
+        let maps: Vec<_> = (0..2)
+            .map(|shard| {
+                let mut map = PriorityMap::new_min();
+                for i in (0..6).filter(|i| i % 2 == shard) {
+                    map.insert(i, i, ());
+                }
+                map
+            })
+            .collect();
+
+        let merged: Vec<_> =
+            super::merge_sorted_by(maps.iter().map(PriorityMap::iter_sorted), crate::Min)
+                .map(|(priority, _, _)| *priority)
+                .collect();
+        assert_eq!(merged, [0, 1, 2, 3, 4, 5]);
+    }
+}
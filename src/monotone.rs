@@ -0,0 +1,233 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+
+use crate::backend::{DAryHeap, HeapBackend};
+use crate::order::{Max, PriorityOrder};
+use crate::PriorityMap;
+
+/// A [`PriorityMap`] whose popped priorities never rise again.
+///
+/// Remembers the priority of the last popped entry and rejects priorities that would pop
+/// before it, as in Dijkstra's algorithm or an event-driven simulation, where a rejected
+/// priority points to a bug in the caller. Together with a
+/// [`BucketQueue`](crate::BucketQueue) backend, this is a monotone bucket queue.
+pub struct MonotonePriorityMap<P, K, V, S = RandomState, O = Max, B = DAryHeap<P>>
+where
+    K: Hash,
+{
+    map: PriorityMap<P, K, V, S, O, B>,
+    /// The priority of the last popped entry.
+    floor: Option<P>,
+}
+
+/// The error returned when a priority would pop before the last popped priority.
+///
+/// Contains the rejected input.
+#[derive(Debug, PartialEq, Eq)]
+pub struct MonotoneError<T>(pub T);
+
+impl<T> fmt::Display for MonotoneError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("priority would pop before the last popped priority")
+    }
+}
+
+impl<T: fmt::Debug> std::error::Error for MonotoneError<T> {}
+
+impl<P, K, V> MonotonePriorityMap<P, K, V>
+where
+    P: PartialOrd,
+    K: Eq + Hash,
+{
+    pub fn new() -> Self {
+        Self::with_order(Max)
+    }
+}
+
+impl<P, K, V> Default for MonotonePriorityMap<P, K, V>
+where
+    P: PartialOrd,
+    K: Eq + Hash,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P, K, V, O> MonotonePriorityMap<P, K, V, RandomState, O>
+where
+    K: Eq + Hash,
+    O: PriorityOrder<P>,
+{
+    pub fn with_order(order: O) -> Self {
+        PriorityMap::with_order(order).into()
+    }
+}
+
+/// Starts checking priorities from the first pop on, without checking existing entries.
+impl<P, K, V, S, O, B> From<PriorityMap<P, K, V, S, O, B>> for MonotonePriorityMap<P, K, V, S, O, B>
+where
+    K: Hash,
+{
+    fn from(map: PriorityMap<P, K, V, S, O, B>) -> Self {
+        Self { map, floor: None }
+    }
+}
+
+impl<P, K, V, S, O, B> MonotonePriorityMap<P, K, V, S, O, B>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+    B: HeapBackend<P>,
+{
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Returns the priority of the last popped entry, below which priorities are rejected.
+    pub fn floor(&self) -> Option<&P> {
+        self.floor.as_ref()
+    }
+
+    /// Returns whether `priority` may be added without popping before the floor.
+    pub fn admits(&self, priority: &P) -> bool {
+        self.floor
+            .as_ref()
+            .is_none_or(|floor| !self.map.order().higher(priority, floor))
+    }
+
+    /// Inserts an entry, replacing the priority and value of an existing entry with the
+    /// same key.
+    ///
+    /// Returns the previous priority and value, or the rejected entry if `priority` would
+    /// pop before the floor.
+    #[allow(clippy::type_complexity)]
+    pub fn insert(
+        &mut self,
+        priority: P,
+        key: K,
+        value: V,
+    ) -> Result<Option<(P, V)>, MonotoneError<(P, K, V)>> {
+        if !self.admits(&priority) {
+            return Err(MonotoneError((priority, key, value)));
+        }
+        Ok(self.map.insert(priority, key, value))
+    }
+
+    /// Changes the priority of an existing entry and returns the old priority.
+    ///
+    /// Returns the rejected priority if it would pop before the floor.
+    pub fn reprioritize<Q>(&mut self, key: &Q, priority: P) -> Result<Option<P>, MonotoneError<P>>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        if !self.admits(&priority) {
+            return Err(MonotoneError(priority));
+        }
+        Ok(self.map.reprioritize(key, priority))
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<(P, V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.map.remove_full(key)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.map.contains_key(key)
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.map.get(key)
+    }
+
+    pub fn get_priority<Q>(&self, key: &Q) -> Option<&P>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.map.get_priority(key)
+    }
+
+    pub fn peek(&self) -> Option<(&P, &K, &V)> {
+        self.map.peek_full()
+    }
+
+    /// Pops the top entry and raises the floor to its priority.
+    pub fn pop(&mut self) -> Option<(P, K, V)>
+    where
+        P: Clone,
+    {
+        let (priority, key, value) = self.map.pop_full()?;
+        self.floor = Some(priority.clone());
+        Some((priority, key, value))
+    }
+
+    /// Returns the underlying map, which no longer checks priorities.
+    pub fn into_inner(self) -> PriorityMap<P, K, V, S, O, B> {
+        self.map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MonotoneError, MonotonePriorityMap};
+    use crate::{BucketQueue, Min, PriorityMap};
+
+    #[test]
+    fn rejects_earlier_priorities() {
+        let mut map = MonotonePriorityMap::with_order(Min);
+        assert_eq!(map.insert(3, "c", ()), Ok(None));
+        assert_eq!(map.insert(1, "a", ()), Ok(None));
+        assert_eq!(map.pop(), Some((1, "a", ())));
+        assert_eq!(map.floor(), Some(&1));
+
+        assert_eq!(map.insert(0, "z", ()), Err(MonotoneError((0, "z", ()))));
+        assert_eq!(map.insert(1, "b", ()), Ok(None));
+        assert_eq!(map.reprioritize(&"c", 0), Err(MonotoneError(0)));
+        assert_eq!(map.reprioritize(&"c", 2), Ok(Some(3)));
+        assert_eq!(map.pop(), Some((1, "b", ())));
+        assert_eq!(map.pop(), Some((2, "c", ())));
+        assert!(!map.admits(&1));
+        assert_eq!(map.pop(), None);
+        assert_eq!(map.floor(), Some(&2));
+    }
+
+    #[test]
+    fn bucket_queue() {
+        let map = PriorityMap::with_order(Min).into_backend::<BucketQueue<u8, 16>>();
+        let mut map = MonotonePriorityMap::from(map);
+        map.insert(0, 0, ()).unwrap();
+        let mut popped = vec![];
+        while let Some((priority, node, ())) = map.pop() {
+            popped.push(priority);
+            if node < 10 {
+                map.insert(priority + 2, node + 2, ()).unwrap();
+                map.insert(priority + 1, node + 1, ()).unwrap();
+            }
+            if priority > 0 {
+                assert!(map.insert(priority - 1, 100, ()).is_err());
+            }
+        }
+        assert!(popped.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(map.floor(), popped.last());
+    }
+}
@@ -0,0 +1,58 @@
+/// Decides which of two priorities is popped first.
+///
+/// Implemented for [`Max`], [`Min`] and any `Fn(&P, &P) -> bool` closure.
+pub trait PriorityOrder<P: ?Sized> {
+    /// Returns `true` if an entry with priority `a` must be popped before one with priority `b`.
+    fn higher(&self, a: &P, b: &P) -> bool;
+}
+
+/// Pops the greatest priority first. This is the default order.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Max;
+
+/// Pops the smallest priority first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Min;
+
+/// Orders `(primary, secondary)` priorities by `primary` under the first order, and
+/// entries with equal primary priorities by `secondary` under the second order.
+///
+/// For example, `ThenBy(Max, Min)` pops the greatest primary priority first and
+/// resolves ties in favour of the smallest secondary priority, independent of the
+/// heap layout.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ThenBy<A, B>(pub A, pub B);
+
+impl<P: PartialOrd + ?Sized> PriorityOrder<P> for Max {
+    fn higher(&self, a: &P, b: &P) -> bool {
+        a > b
+    }
+}
+
+impl<P: PartialOrd + ?Sized> PriorityOrder<P> for Min {
+    fn higher(&self, a: &P, b: &P) -> bool {
+        a < b
+    }
+}
+
+impl<P1, P2, A, B> PriorityOrder<(P1, P2)> for ThenBy<A, B>
+where
+    A: PriorityOrder<P1>,
+    B: PriorityOrder<P2>,
+{
+    fn higher(&self, a: &(P1, P2), b: &(P1, P2)) -> bool {
+        if self.0.higher(&a.0, &b.0) {
+            return true;
+        }
+        !self.0.higher(&b.0, &a.0) && self.1.higher(&a.1, &b.1)
+    }
+}
+
+impl<P: ?Sized, F> PriorityOrder<P> for F
+where
+    F: Fn(&P, &P) -> bool,
+{
+    fn higher(&self, a: &P, b: &P) -> bool {
+        self(a, b)
+    }
+}
@@ -1,208 +1,2666 @@
-use std::collections::{hash_map, HashMap};
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
 
-pub struct PriorityMap<P, K, V>
+use hashbrown::HashTable;
+
+use crate::backend::{
+    from_idx, permute, to_idx, BucketQueue, DAryHeap, DoubleEndedBackend, HeapBackend, Idx,
+    MinMaxHeap, PairingHeap, Ranking,
+};
+use crate::order::{Max, Min, PriorityOrder};
+use crate::storage::{Storage, Timestamped};
+
+mod batch;
+mod error;
+pub mod iter;
+mod peek_mut;
+#[cfg(feature = "rand")]
+mod random;
+
+pub use batch::BatchInsert;
+pub use error::OccupiedError;
+use iter::{
+    Drain, DrainSorted, DrainTop, ExtractIf, IntoIter, IntoKeys, IntoValues, Iter, IterMut,
+    IterSorted, Keys, PopWhile, Priorities, Starving, Values,
+};
+pub use peek_mut::PeekMut;
+
+/// A map from keys to values that pops entries by priority.
+///
+/// Keys and values are stored in slots, indexed by key. The priorities are ordered by the
+/// heap backend `B`, a binary [`DAryHeap`] by default, which only refers to the slots by
+/// index. Sifting never moves keys or values; a value is moved only when another entry is
+/// removed and its slot fills the gap.
+///
+/// Values are stored as `W`, which is `V` itself by default, see [`Storage`].
+#[derive(Clone)]
+pub struct PriorityMap<P, K, V, S = RandomState, O = Max, B = DAryHeap<P>, W = V>
 where
-    K: std::hash::Hash,
+    K: Hash,
 {
-    heap: Vec<Entry<P, K, V>>,
-    map: HashMap<K, usize>,
+    /// Orders the priorities of the slots.
+    heap: B,
+    /// Keys and values in arbitrary order. Sifting the heap never moves them.
+    slots: Vec<Slot<K, W>>,
+    /// Slot indices, hashed by the cached hashes of the keys stored there.
+    index: HashTable<Idx>,
+    hasher: S,
+    ranking: Ranking<O>,
+    sequencer: Sequencer,
+    value: PhantomData<(P, V)>,
 }
 
+/// A [`PriorityMap`] that pops the entry with the smallest priority first.
+///
+/// Priorities are inserted as they are, without wrapping them in
+/// [`Reverse`](std::cmp::Reverse). Create one with [`PriorityMap::new_min`] or
+/// [`Default`].
+pub type MinPriorityMap<P, K, V, S = RandomState> = PriorityMap<P, K, V, S, Min>;
+
+/// A [`PriorityMap`] that stores every value in its own allocation, see [`Storage`].
+///
+/// Create one with [`Default`], by collecting an iterator, or with
+/// [`PriorityMap::into_storage`].
+pub type BoxedPriorityMap<P, K, V, S = RandomState, O = Max> =
+    PriorityMap<P, K, V, S, O, DAryHeap<P>, Box<V>>;
+
+/// A [`PriorityMap`] that records when every entry was inserted, see [`Timestamped`].
+///
+/// Finds entries that wait too long with [`longest_waiting`](PriorityMap::longest_waiting)
+/// and [`starving`](PriorityMap::starving). Create one with [`Default`], by collecting an
+/// iterator, or with [`PriorityMap::into_storage`].
+pub type TimestampedPriorityMap<P, K, V, S = RandomState, O = Max> =
+    PriorityMap<P, K, V, S, O, DAryHeap<P>, Timestamped<V>>;
+
+/// A [`PriorityMap`] backed by a [`PairingHeap`], for workloads dominated by raised
+/// priorities.
+///
+/// Create one with [`Default`], by collecting an iterator, or with
+/// [`PriorityMap::into_backend`].
+pub type PairingPriorityMap<P, K, V, S = RandomState, O = Max> =
+    PriorityMap<P, K, V, S, O, PairingHeap<P>>;
+
+/// A [`PriorityMap`] backed by a [`BucketQueue`], for small integer priorities below `N`.
+///
+/// Create one with [`Default`], by collecting an iterator, or with
+/// [`PriorityMap::into_backend`].
+pub type BucketPriorityMap<P, K, V, const N: usize, S = RandomState, O = Max> =
+    PriorityMap<P, K, V, S, O, BucketQueue<P, N>>;
+
+/// A [`PriorityMap`] backed by a [`MinMaxHeap`], which pops from both ends with
+/// [`pop`](PriorityMap::pop) and [`pop_min`](PriorityMap::pop_min).
+///
+/// Create one with [`Default`], by collecting an iterator, or with
+/// [`PriorityMap::into_backend`].
+pub type DoubleEndedPriorityMap<P, K, V, S = RandomState, O = Max> =
+    PriorityMap<P, K, V, S, O, MinMaxHeap<P>>;
+
+/// A [`PriorityMap`] backed by a [`Weighted`](crate::Weighted) binary heap, for
+/// [`pop_weighted`](PriorityMap::pop_weighted) in `O(log n)`.
+///
+/// Create one with [`Default`], by collecting an iterator, or with
+/// [`PriorityMap::into_backend`].
+#[cfg(feature = "rand")]
+pub type WeightedPriorityMap<P, K, V, S = RandomState, O = Max> =
+    PriorityMap<P, K, V, S, O, crate::Weighted<DAryHeap<P>>>;
+
+/// A [`PriorityMap`] using the fast, non-DoS-resistant hasher from `rustc-hash`.
+#[cfg(feature = "rustc-hash")]
+pub type FxPriorityMap<P, K, V> = PriorityMap<P, K, V, rustc_hash::FxBuildHasher>;
+
+/// A [`PriorityMap`] using the hasher from `ahash`.
+#[cfg(feature = "ahash")]
+pub type AHashPriorityMap<P, K, V> = PriorityMap<P, K, V, ahash::RandomState>;
+
 impl<P, K, V> PriorityMap<P, K, V>
 where
-    P: PartialOrd + Clone,
-    K: Eq + std::hash::Hash + Clone,
-    V: Ord,
+    P: PartialOrd,
+    K: Eq + Hash,
 {
     pub fn new() -> Self {
+        Self::with_parts(0, RandomState::new(), Max)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_parts(capacity, RandomState::new(), Max)
+    }
+
+    /// Creates an empty map that pops the entry with the smallest priority first.
+    pub fn new_min() -> MinPriorityMap<P, K, V> {
+        PriorityMap::with_parts(0, RandomState::new(), Min)
+    }
+}
+
+impl<P, K, V, O> PriorityMap<P, K, V, RandomState, O>
+where
+    K: Eq + Hash,
+    O: PriorityOrder<P>,
+{
+    /// Creates an empty map that pops entries according to `order` instead of by
+    /// descending priority.
+    ///
+    /// `order` is either a [`PriorityOrder`] or a closure returning whether its first
+    /// argument must be popped before its second.
+    pub fn with_order(order: O) -> Self {
+        Self::with_order_and_hasher(order, RandomState::new())
+    }
+}
+
+impl<P, K, V, S, O> PriorityMap<P, K, V, S, O>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+{
+    /// Creates an empty map which uses `hasher` to hash keys.
+    pub fn with_hasher(hasher: S) -> Self
+    where
+        O: Default,
+    {
+        Self::with_order_and_hasher(O::default(), hasher)
+    }
+
+    /// Creates an empty map with space for at least `capacity` entries, which uses
+    /// `hasher` to hash keys.
+    pub fn with_capacity_and_hasher(capacity: usize, hasher: S) -> Self
+    where
+        O: Default,
+    {
+        Self::with_parts(capacity, hasher, O::default())
+    }
+
+    /// Creates an empty map that pops entries according to `order` and uses `hasher`
+    /// to hash keys.
+    pub fn with_order_and_hasher(order: O, hasher: S) -> Self {
+        Self::with_parts(0, hasher, order)
+    }
+}
+
+impl<P, K, V, S, O, B, W> PriorityMap<P, K, V, S, O, B, W>
+where
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+{
+    pub fn hasher(&self) -> &S {
+        &self.hasher
+    }
+
+    /// Creates an empty map with space for at least `capacity` entries.
+    fn with_parts(capacity: usize, hasher: S, order: O) -> Self {
+        let mut heap = B::default();
+        heap.reserve(capacity);
         Self {
-            heap: vec![],
-            map: HashMap::new(),
+            heap,
+            slots: Vec::with_capacity(capacity),
+            index: HashTable::with_capacity(capacity),
+            hasher,
+            ranking: Ranking::new(order),
+            sequencer: Sequencer::default(),
+            value: PhantomData,
+        }
+    }
+
+    /// Returns the order that decides which entry is popped first.
+    pub fn order(&self) -> &O {
+        &self.ranking.order
+    }
+
+    /// Converts the map into one with the heap backend `C`, rebuilding the heap in `O(n)`.
+    ///
+    /// ```
+    /// use prioritymap::{PairingPriorityMap, PriorityMap};
+    ///
+    /// let mut map: PairingPriorityMap<_, _, _> = PriorityMap::new().into_backend();
+    /// map.insert(1, "a", ());
+    /// map.insert(3, "b", ());
+    /// assert_eq!(map.pop_full(), Some((3, "b", ())));
+    /// ```
+    pub fn into_backend<C: HeapBackend<P>>(self) -> PriorityMap<P, K, V, S, O, C, W> {
+        let mut heap = C::default();
+        heap.reserve(self.heap.len());
+        for (priority, sequence) in self.heap.into_nodes() {
+            heap.push_unordered(priority, sequence);
+        }
+        heap.rebuild(&self.ranking);
+        PriorityMap {
+            heap,
+            slots: self.slots,
+            index: self.index,
+            hasher: self.hasher,
+            ranking: self.ranking,
+            sequencer: self.sequencer,
+            value: PhantomData,
+        }
+    }
+
+    /// Converts the map into one whose heap nodes have `E` children, rebuilding the heap
+    /// in `O(n)`.
+    ///
+    /// A 4-ary or 8-ary heap is shallower than a binary one, so it sifts down through
+    /// fewer levels and touches fewer cache lines in large maps.
+    ///
+    /// ```
+    /// use prioritymap::PriorityMap;
+    ///
+    /// let mut map = PriorityMap::new().into_arity::<4>();
+    /// map.insert(1, "a", ());
+    /// map.insert(3, "b", ());
+    /// assert_eq!(map.pop_full(), Some((3, "b", ())));
+    /// ```
+    pub fn into_arity<const E: usize>(self) -> PriorityMap<P, K, V, S, O, DAryHeap<P, E>, W> {
+        self.into_backend()
+    }
+
+    /// Converts the map into one that stores its values as `X`, see [`Storage`].
+    ///
+    /// ```
+    /// use prioritymap::{BoxedPriorityMap, PriorityMap};
+    ///
+    /// let mut map: BoxedPriorityMap<_, _, _> = PriorityMap::new().into_storage();
+    /// map.insert(1, "a", [0u8; 512]);
+    /// assert_eq!(map.pop(), Some([0; 512]));
+    /// ```
+    pub fn into_storage<X: Storage<V>>(self) -> PriorityMap<P, K, V, S, O, B, X> {
+        let slots = self
+            .slots
+            .into_iter()
+            .map(|slot| Slot {
+                key: slot.key,
+                value: X::store(slot.value.into_value()),
+                hash: slot.hash,
+            })
+            .collect();
+        PriorityMap {
+            heap: self.heap,
+            slots,
+            index: self.index,
+            hasher: self.hasher,
+            ranking: self.ranking,
+            sequencer: self.sequencer,
+            value: PhantomData,
+        }
+    }
+
+    /// Makes entries of equal priority pop in the order their keys were first inserted.
+    ///
+    /// Changing the priority or value of an existing entry keeps its place among equal
+    /// priorities. Entries moved in by [`append`](Self::append) or
+    /// [`merge_with`](Self::merge_with) count as inserted at that point.
+    pub fn set_stable(&mut self, stable: bool) {
+        if stable != self.ranking.stable {
+            self.ranking.stable = stable;
+            self.rebuild();
+        }
+    }
+
+    /// Returns whether entries of equal priority pop in insertion order.
+    pub fn is_stable(&self) -> bool {
+        self.ranking.stable
+    }
+
+    /// Returns the number of entries the map can hold without reallocating.
+    ///
+    /// This is the smallest of the capacities of the heap, the slots and the key index.
+    pub fn capacity(&self) -> usize {
+        self.heap
+            .capacity()
+            .min(self.slots.capacity())
+            .min(self.index.capacity())
+    }
+
+    /// Reserves capacity for at least `additional` more entries.
+    pub fn reserve(&mut self, additional: usize) {
+        self.heap.reserve(additional);
+        self.slots.reserve(additional);
+        let slots = &self.slots;
+        self.index
+            .reserve(additional, |&slot| slots[from_idx(slot)].hash);
+    }
+
+    /// Shrinks the allocated memory as much as possible.
+    pub fn shrink_to_fit(&mut self) {
+        self.heap.shrink_to_fit();
+        self.slots.shrink_to_fit();
+        let slots = &self.slots;
+        self.index.shrink_to_fit(|&slot| slots[from_idx(slot)].hash);
+    }
+
+    /// Rebuilds the heap from scratch in `O(n)` and shrinks the allocated memory.
+    pub fn compact(&mut self) {
+        self.rebuild();
+        self.shrink_to_fit();
+    }
+
+    pub fn len(&self) -> usize {
+        debug_assert_eq!(self.index.len(), self.heap.len());
+        debug_assert_eq!(self.slots.len(), self.heap.len());
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all entries, keeping the allocated memory.
+    pub fn clear(&mut self) {
+        self.heap.clear();
+        self.slots.clear();
+        self.index.clear();
+    }
+
+    /// Inserts an entry, replacing the priority and value of an existing entry with the same key.
+    ///
+    /// Returns the previous priority and value if the key was already present.
+    pub fn insert(&mut self, priority: P, key: K, value: V) -> Option<(P, V)> {
+        let hash = self.hasher.hash_one(&key);
+        let Some(slot_index) = self.find(hash, &key) else {
+            self.push_new(hash, priority, key, W::store(value));
+            return None;
+        };
+        let slot = &mut self.slots[slot_index];
+        debug_assert!(slot.key == key);
+        let value = std::mem::replace(slot.value.value_mut(), value);
+        let priority = self.reprioritize_slot(slot_index, priority);
+        Some((priority, value))
+    }
+
+    /// Replaces the priority and value of an existing entry and returns the old ones with
+    /// the key, or returns the entry in the error if the key does not exist.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn replace_full(
+        &mut self,
+        priority: P,
+        key: K,
+        value: V,
+    ) -> Result<(P, K, V), (P, K, V)> {
+        let Some(slot) = self.slot(&key) else {
+            return Err((priority, key, value));
+        };
+        let value = std::mem::replace(self.slots[slot].value.value_mut(), value);
+        let priority = self.reprioritize_slot(slot, priority);
+        Ok((priority, key, value))
+    }
+
+    /// Inserts a new entry, or returns it in the error if the key already exists.
+    pub fn try_insert(
+        &mut self,
+        priority: P,
+        key: K,
+        value: V,
+    ) -> Result<(), OccupiedError<P, K, V>> {
+        let hash = self.hasher.hash_one(&key);
+        if self.find(hash, &key).is_some() {
+            return Err(OccupiedError {
+                priority,
+                key,
+                value,
+            });
+        }
+        self.push_new(hash, priority, key, W::store(value));
+        Ok(())
+    }
+
+    /// Inserts an entry unless the key already exists with a priority greater than or
+    /// equal to `priority`.
+    ///
+    /// Returns whether the entry was inserted.
+    pub fn insert_if_higher(&mut self, priority: P, key: K, value: V) -> bool {
+        let hash = self.hasher.hash_one(&key);
+        let Some(slot) = self.find(hash, &key) else {
+            self.push_new(hash, priority, key, W::store(value));
+            return true;
+        };
+        if !self
+            .ranking
+            .order
+            .higher(&priority, self.heap.priority(slot))
+        {
+            return false;
+        }
+        self.heap.check_priority(&priority);
+        *self.heap.priority_mut(slot) = priority;
+        self.heap.raise(slot, &self.ranking);
+        *self.slots[slot].value.value_mut() = value;
+        true
+    }
+
+    /// Updates the priority and value of an existing entry with `update`, or inserts the
+    /// priority and value returned by `insert` if the key does not exist.
+    pub fn upsert<I, U>(&mut self, key: K, insert: I, update: U)
+    where
+        I: FnOnce() -> (P, V),
+        U: FnOnce(&mut P, &mut V),
+    {
+        let hash = self.hasher.hash_one(&key);
+        let Some(slot) = self.find(hash, &key) else {
+            let (priority, value) = insert();
+            self.push_new(hash, priority, key, W::store(value));
+            return;
+        };
+        update(
+            self.heap.priority_mut(slot),
+            self.slots[slot].value.value_mut(),
+        );
+        self.heap.restore(slot, &self.ranking);
+    }
+
+    /// Iterates over all entries in arbitrary order.
+    pub fn iter(&self) -> Iter<'_, P, K, V, B, W> {
+        Iter {
+            heap: &self.heap,
+            inner: self.slots.iter().enumerate(),
+            value: PhantomData,
+        }
+    }
+
+    /// Iterates over all entries in arbitrary order, with mutable access to values.
+    ///
+    /// Priorities cannot be changed through this iterator, use
+    /// [`reprioritize`](Self::reprioritize) instead.
+    pub fn iter_mut(&mut self) -> IterMut<'_, P, K, V, B, W> {
+        IterMut {
+            heap: &self.heap,
+            inner: self.slots.iter_mut().enumerate(),
+            value: PhantomData,
+        }
+    }
+
+    /// Iterates over all keys in arbitrary order.
+    pub fn keys(&self) -> Keys<'_, P, K, V, B, W> {
+        Keys { inner: self.iter() }
+    }
+
+    /// Iterates over all values in arbitrary order.
+    pub fn values(&self) -> Values<'_, P, K, V, B, W> {
+        Values { inner: self.iter() }
+    }
+
+    /// Iterates over all priorities in arbitrary order.
+    pub fn priorities(&self) -> Priorities<'_, P, K, V, B, W> {
+        Priorities { inner: self.iter() }
+    }
+
+    /// Iterates over all entries by descending priority, without modifying the map.
+    pub fn iter_sorted(&self) -> IterSorted<'_, P, K, V, O, B, W> {
+        IterSorted::new(&self.heap, &self.slots, &self.ranking)
+    }
+
+    /// Returns the `n` entries with the highest priority by descending priority,
+    /// without modifying the map.
+    ///
+    /// Runs in `O(n log n)`, independent of the size of the map.
+    pub fn peek_top_n(&self, n: usize) -> Vec<(&P, &K, &V)> {
+        let mut top = Vec::with_capacity(n.min(self.heap.len()));
+        top.extend(self.iter_sorted().take(n));
+        top
+    }
+
+    /// Returns the entry that would be popped `n`-th, counting from zero, without modifying
+    /// the map.
+    ///
+    /// Walks the heap from the top for small `n`, and otherwise selects the entry from all
+    /// of them in `O(len)`.
+    pub fn peek_nth(&self, n: usize) -> Option<(&P, &K, &V)> {
+        let len = self.heap.len();
+        if n >= len {
+            return None;
+        }
+        let depth = (usize::BITS - n.leading_zeros()) as usize;
+        if n.saturating_mul(depth) < len {
+            return self.iter_sorted().nth(n);
+        }
+        let mut slots: Vec<_> = (0..len).collect();
+        let (_, &mut slot, _) = slots.select_nth_unstable_by(n, |&a, &b| self.compare(a, b));
+        let entry = &self.slots[slot];
+        Some((self.heap.priority(slot), &entry.key, entry.value.value()))
+    }
+
+    /// Returns the priority that a fraction `q` of the entries rank below by the order of
+    /// the map, using the nearest rank, without modifying the map.
+    ///
+    /// `q = 0.0` is the priority that pops last and `q = 1.0` the one that pops first, so
+    /// `0.5` is the median. With [`Max`], these are the usual quantiles. Runs in `O(len)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `q` is not between `0.0` and `1.0`.
+    pub fn priority_quantile(&self, q: f64) -> Option<&P> {
+        assert!((0.0..=1.0).contains(&q), "quantile must be between 0 and 1");
+        let last = self.heap.len().checked_sub(1)?;
+        let n = ((1.0 - q) * last as f64).round() as usize;
+        let (priority, _, _) = self.peek_nth(n)?;
+        Some(priority)
+    }
+
+    /// Counts the priorities between consecutive `bounds`, which must be in ascending
+    /// order.
+    ///
+    /// Returns `bounds.len() + 1` counts: the number of priorities below `bounds[0]`, then
+    /// the number from `bounds[i - 1]` up to but excluding `bounds[i]`, and finally the
+    /// number from the last bound up. Runs in `O(len log bounds)`.
+    pub fn priority_histogram(&self, bounds: &[P]) -> Vec<usize>
+    where
+        P: PartialOrd,
+    {
+        let mut counts = vec![0; bounds.len() + 1];
+        for priority in self.priorities() {
+            counts[bounds.partition_point(|bound| bound <= priority)] += 1;
+        }
+        counts
+    }
+
+    /// Removes all entries and yields them in arbitrary order, keeping the allocated memory.
+    pub fn drain(&mut self) -> Drain<'_, P, K, V, B, W> {
+        self.index.clear();
+        Drain {
+            inner: self.heap.drain().zip(self.slots.drain(..)),
+            value: PhantomData,
+        }
+    }
+
+    /// Consumes the map and yields its keys in arbitrary order.
+    pub fn into_keys(self) -> IntoKeys<P, K, V, B, W> {
+        IntoKeys {
+            inner: self.into_iter(),
+        }
+    }
+
+    /// Consumes the map and yields its values in arbitrary order.
+    pub fn into_values(self) -> IntoValues<P, K, V, B, W> {
+        IntoValues {
+            inner: self.into_iter(),
+        }
+    }
+
+    /// Consumes the map and returns all entries sorted by descending priority.
+    ///
+    /// The priorities are sorted by the heap first, then the keys and values are put into
+    /// the same order in place.
+    pub fn into_sorted_vec(self) -> Vec<(P, K, V)> {
+        let sorted = self.heap.into_sorted(&self.ranking);
+        let mut slots = self.slots;
+        let mut sources: Vec<_> = sorted.iter().map(|&(_, slot)| slot).collect();
+        permute(&mut slots, &mut sources);
+        sorted
+            .into_iter()
+            .zip(slots)
+            .map(|((priority, _), slot)| (priority, slot.key, slot.value.into_value()))
+            .collect()
+    }
+
+    /// Removes entries by descending priority as the returned iterator is advanced.
+    ///
+    /// Entries that were not consumed are removed when the iterator is dropped.
+    pub fn drain_sorted(&mut self) -> DrainSorted<'_, P, K, V, S, O, B, W> {
+        DrainSorted { map: self }
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.slot(key).is_some()
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let slot = self.slot(key)?;
+        Some(self.slots[slot].value.value())
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let slot = self.slot(key)?;
+        Some(self.slots[slot].value.value_mut())
+    }
+
+    pub fn get_priority<Q>(&self, key: &Q) -> Option<&P>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        Some(self.heap.priority(self.slot(key)?))
+    }
+
+    pub fn get_full<Q>(&self, key: &Q) -> Option<(&P, &K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let slot = self.slot(key)?;
+        let entry = &self.slots[slot];
+        Some((self.heap.priority(slot), &entry.key, entry.value.value()))
+    }
+
+    /// Removes the `n` entries with the highest priority and yields them by descending
+    /// priority as the returned iterator is advanced.
+    ///
+    /// All `n` entries are removed even if the iterator is not fully consumed.
+    pub fn drain_top(&mut self, n: usize) -> DrainTop<'_, P, K, V, S, O, B, W> {
+        DrainTop {
+            map: self,
+            remaining: n,
+        }
+    }
+
+    /// Replaces the value of an existing entry without changing its priority.
+    ///
+    /// Returns the previous value, or `None` if the key does not exist.
+    pub fn replace_value<Q>(&mut self, key: &Q, value: V) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        Some(std::mem::replace(self.get_mut(key)?, value))
+    }
+
+    #[doc(alias = "peek_max")]
+    pub fn peek(&self) -> Option<&V> {
+        Some(self.slots[self.heap.top()?].value.value())
+    }
+
+    pub fn peek_full(&self) -> Option<(&P, &K, &V)> {
+        let slot = self.heap.top()?;
+        let entry = &self.slots[slot];
+        Some((self.heap.priority(slot), &entry.key, entry.value.value()))
+    }
+
+    /// Returns a guard that inserts entries without sifting and rebuilds the heap once
+    /// when it is dropped, see [`BatchInsert`].
+    ///
+    /// Worth it for batches that are large compared to the map; for a handful of entries,
+    /// [`insert`](Self::insert) is cheaper.
+    pub fn batch_insert(&mut self) -> BatchInsert<'_, P, K, V, S, O, B, W> {
+        BatchInsert {
+            map: self,
+            dirty: false,
+        }
+    }
+
+    /// Returns a guard for modifying the top entry, see [`PeekMut`].
+    pub fn peek_mut(&mut self) -> Option<PeekMut<'_, P, K, V, S, O, B, W>> {
+        let slot = self.heap.top()?;
+        Some(PeekMut {
+            map: self,
+            slot,
+            sift: false,
+        })
+    }
+
+    #[doc(alias = "pop_max")]
+    pub fn pop(&mut self) -> Option<V> {
+        Some(self.pop_entry()?.value.into_value())
+    }
+
+    pub fn pop_full(&mut self) -> Option<(P, K, V)> {
+        let entry = self.pop_entry()?;
+        Some((entry.priority, entry.key, entry.value.into_value()))
+    }
+
+    /// Pops up to `n` entries by descending priority.
+    pub fn pop_batch(&mut self, n: usize) -> Vec<(P, K, V)> {
+        let mut batch = Vec::with_capacity(n.min(self.heap.len()));
+        batch.extend((0..n).map_while(|_| self.pop_full()));
+        batch
+    }
+
+    /// Pops the top entry only if `pred` returns `true` for it.
+    pub fn pop_if<F>(&mut self, pred: F) -> Option<(P, K, V)>
+    where
+        F: FnOnce(&P, &K, &V) -> bool,
+    {
+        let (priority, key, value) = self.peek_full()?;
+        if pred(priority, key, value) {
+            self.pop_full()
+        } else {
+            None
+        }
+    }
+
+    /// Pops entries by descending priority as the returned iterator is advanced,
+    /// stopping at the first entry for which `pred` returns `false`.
+    pub fn pop_while<F>(&mut self, pred: F) -> PopWhile<'_, P, K, V, F, S, O, B, W>
+    where
+        F: FnMut(&P, &K, &V) -> bool,
+    {
+        PopWhile { map: self, pred }
+    }
+
+    fn pop_entry(&mut self) -> Option<Entry<P, K, W>> {
+        let Some(slot) = self.heap.top() else {
+            debug_assert!(self.index.is_empty());
+            return None;
+        };
+        Some(self.remove_slot(slot))
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        Some(self.remove_entry(key)?.value.into_value())
+    }
+
+    pub fn remove_full<Q>(&mut self, key: &Q) -> Option<(P, V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let entry = self.remove_entry(key)?;
+        Some((entry.priority, entry.value.into_value()))
+    }
+
+    /// Removes all entries with the given keys and returns how many were removed.
+    ///
+    /// When removing a large fraction of the map, the heap is rebuilt once instead of
+    /// sifting after every removal.
+    pub fn remove_batch<I>(&mut self, keys: I) -> usize
+    where
+        I: IntoIterator<Item = K>,
+    {
+        let keys: Vec<_> = keys.into_iter().collect();
+        let depth = (usize::BITS - self.heap.len().leading_zeros()) as usize;
+        if keys.len().saturating_mul(depth) < self.heap.len() {
+            return keys
+                .iter()
+                .filter(|key| self.remove_entry(key).is_some())
+                .count();
+        }
+
+        let len = self.heap.len();
+        for key in &keys {
+            if let Some(slot) = self.slot(key) {
+                self.remove_slot_unordered(slot);
+            }
+        }
+        let removed = len - self.heap.len();
+        if removed > 0 {
+            self.rebuild();
+        }
+        removed
+    }
+
+    fn remove_entry<Q>(&mut self, key: &Q) -> Option<Entry<P, K, W>>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let slot = self.slot(key)?;
+        Some(self.remove_slot(slot))
+    }
+
+    /// Keeps only the entries for which `f` returns `true`.
+    ///
+    /// The heap is restored with a single `O(n)` rebuild after filtering.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&P, &K, &mut V) -> bool,
+    {
+        self.extract_if(|priority, key, value| !f(priority, key, value))
+            .for_each(drop);
+    }
+
+    /// Removes the entries for which `pred` returns `true` and yields them as the
+    /// returned iterator is advanced.
+    ///
+    /// Entries that are not visited before the iterator is dropped stay in the map.
+    pub fn extract_if<F>(&mut self, pred: F) -> ExtractIf<'_, P, K, V, F, S, O, B, W>
+    where
+        F: FnMut(&P, &K, &mut V) -> bool,
+    {
+        ExtractIf {
+            map: self,
+            slot: 0,
+            removed: false,
+            pred,
+        }
+    }
+
+    /// Removes all entries except for the `n` with the highest priority.
+    ///
+    /// Among entries of equal priority, it is unspecified which ones are kept, unless the
+    /// map is [stable](Self::set_stable).
+    pub fn truncate_top(&mut self, n: usize) {
+        if n >= self.heap.len() {
+            return;
+        }
+        let mut slots: Vec<_> = (0..self.heap.len()).collect();
+        if n > 0 {
+            slots.select_nth_unstable_by(n - 1, |&a, &b| self.compare(a, b));
+        }
+        let mut removed = slots.split_off(n);
+        // Removing the highest slots first keeps the other slots to remove in place.
+        removed.sort_unstable_by(|a, b| b.cmp(a));
+        for slot in removed {
+            self.remove_slot_unordered(slot);
+        }
+        self.rebuild();
+    }
+
+    /// Moves all entries with a priority greater than or equal to `threshold` into a new map.
+    pub fn split_off_by_priority(&mut self, threshold: &P) -> Self
+    where
+        S: Clone,
+        O: Clone,
+    {
+        let (split, keep): (Vec<_>, Vec<_>) = self
+            .take_entries()
+            .into_iter()
+            .partition(|entry| !self.ranking.order.higher(threshold, &entry.priority));
+        self.fill(keep);
+        Self::from_unique_entries(
+            split,
+            self.hasher.clone(),
+            self.ranking.clone(),
+            self.sequencer,
+        )
+    }
+
+    /// Splits the map into the entries for which `pred` returns `true` and the rest.
+    pub fn partition<F>(mut self, mut pred: F) -> (Self, Self)
+    where
+        S: Clone,
+        O: Clone,
+        F: FnMut(&P, &K, &V) -> bool,
+    {
+        let (matching, rest): (Vec<_>, Vec<_>) = self
+            .take_entries()
+            .into_iter()
+            .partition(|entry| pred(&entry.priority, &entry.key, entry.value.value()));
+        let matching = Self::from_unique_entries(
+            matching,
+            self.hasher.clone(),
+            self.ranking.clone(),
+            self.sequencer,
+        );
+        self.fill(rest);
+        (matching, self)
+    }
+
+    /// Moves all entries from `other` into `self`, leaving `other` empty.
+    ///
+    /// Entries from `other` replace entries with the same key in `self`.
+    pub fn append(&mut self, other: &mut Self) {
+        if self.heap.is_empty() {
+            std::mem::swap(&mut self.heap, &mut other.heap);
+            std::mem::swap(&mut self.slots, &mut other.slots);
+            std::mem::swap(&mut self.index, &mut other.index);
+            std::mem::swap(&mut self.hasher, &mut other.hasher);
+            std::mem::swap(&mut self.ranking.order, &mut other.ranking.order);
+            self.sequencer.next = self.sequencer.next.max(other.sequencer.next);
+            if self.ranking.stable && !other.ranking.stable {
+                self.rebuild();
+            }
+            return;
+        }
+        let total = self.heap.len() + other.heap.len();
+        let depth = (usize::BITS - total.leading_zeros()) as usize;
+        if other.heap.len().saturating_mul(depth) < total {
+            for entry in other.take_entries() {
+                self.insert_stored(entry.priority, entry.key, entry.value);
+            }
+            return;
+        }
+
+        self.reserve(other.heap.len());
+        // The cached hashes of `other` come from its own hasher, so they are recomputed.
+        for entry in other.take_entries() {
+            self.push_stored_unordered(entry.priority, entry.key, entry.value);
+        }
+        self.rebuild();
+    }
+
+    /// Moves all entries from `other` into `self`.
+    ///
+    /// For keys present in both maps, `f` receives the old priority and value followed by
+    /// the new priority and value, and returns the priority and value to keep.
+    pub fn merge_with<F>(&mut self, mut other: Self, mut f: F)
+    where
+        F: FnMut(P, V, P, V) -> (P, V),
+    {
+        let total = self.heap.len() + other.heap.len();
+        let depth = (usize::BITS - total.leading_zeros()) as usize;
+        if other.heap.len().saturating_mul(depth) < total {
+            for entry in other.take_entries() {
+                match self.remove_entry(&entry.key) {
+                    Some(old) => {
+                        let (priority, value) = merge_stored(
+                            &mut f,
+                            old.priority,
+                            old.value,
+                            entry.priority,
+                            entry.value,
+                        );
+                        self.insert_stored(priority, entry.key, value);
+                    }
+                    None => {
+                        self.insert_stored(entry.priority, entry.key, entry.value);
+                    }
+                }
+            }
+            return;
+        }
+
+        self.reserve(other.heap.len());
+        for entry in other.take_entries() {
+            let hash = self.hasher.hash_one(&entry.key);
+            let entry = match self.find(hash, &entry.key) {
+                Some(slot) => {
+                    // Move the old entry out, the heap property is restored below.
+                    let old = self.remove_slot_unordered(slot);
+                    let (priority, value) =
+                        merge_stored(&mut f, old.priority, old.value, entry.priority, entry.value);
+                    Entry {
+                        priority,
+                        key: old.key,
+                        value,
+                        hash,
+                        sequence: self.sequencer.next(),
+                    }
+                }
+                None => Entry {
+                    hash,
+                    sequence: self.sequencer.next(),
+                    ..entry
+                },
+            };
+            self.push_entry(entry);
+        }
+        self.rebuild();
+    }
+
+    pub fn reprioritize<Q>(&mut self, key: &Q, priority: P) -> Option<P>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let slot = self.slot(key)?;
+        Some(self.reprioritize_slot(slot, priority))
+    }
+
+    /// Changes the priority of an existing entry only if `priority` is strictly higher
+    /// than its current priority.
+    ///
+    /// Returns whether the priority was changed.
+    pub fn reprioritize_if_higher<Q>(&mut self, key: &Q, priority: P) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let Some(slot) = self.slot(key) else {
+            return false;
+        };
+        if !self
+            .ranking
+            .order
+            .higher(&priority, self.heap.priority(slot))
+        {
+            return false;
+        }
+        self.heap.check_priority(&priority);
+        *self.heap.priority_mut(slot) = priority;
+        self.heap.raise(slot, &self.ranking);
+        true
+    }
+
+    /// Changes the priority of an existing entry in place with `f`.
+    ///
+    /// Returns `false` if the key does not exist.
+    pub fn reprioritize_by<Q, F>(&mut self, key: &Q, f: F) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+        F: FnOnce(&mut P),
+    {
+        let Some(slot) = self.slot(key) else {
+            return false;
+        };
+        f(self.heap.priority_mut(slot));
+        self.heap.restore(slot, &self.ranking);
+        true
+    }
+
+    /// Lets `f` change the priority of every entry, then restores the heap with a single
+    /// `O(n)` rebuild.
+    pub fn reprioritize_all<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &V, &mut P),
+    {
+        for (i, slot) in self.slots.iter().enumerate() {
+            f(&slot.key, slot.value.value(), self.heap.priority_mut(i));
+        }
+        self.rebuild();
+    }
+
+    /// Exchanges the priorities of two existing entries.
+    ///
+    /// Returns `false` if either key does not exist.
+    pub fn swap_priorities<Q>(&mut self, key_a: &Q, key_b: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let (Some(slot_a), Some(slot_b)) = (self.slot(key_a), self.slot(key_b)) else {
+            return false;
+        };
+        if slot_a != slot_b {
+            self.heap.swap_priorities(slot_a, slot_b, &self.ranking);
+        }
+        true
+    }
+
+    /// Adds `delta` to the priority of an existing entry.
+    ///
+    /// Returns `false` if the key does not exist.
+    pub fn increase_priority<Q>(&mut self, key: &Q, delta: P) -> bool
+    where
+        P: std::ops::AddAssign,
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.reprioritize_by(key, |priority| *priority += delta)
+    }
+
+    /// Subtracts `delta` from the priority of an existing entry.
+    ///
+    /// Returns `false` if the key does not exist.
+    pub fn decrease_priority<Q>(&mut self, key: &Q, delta: P) -> bool
+    where
+        P: std::ops::SubAssign,
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.reprioritize_by(key, |priority| *priority -= delta)
+    }
+
+    /// Replaces the priority of `slot` and returns the old one.
+    fn reprioritize_slot(&mut self, slot: usize, mut priority: P) -> P {
+        self.heap.check_priority(&priority);
+        let target = self.heap.priority_mut(slot);
+        std::mem::swap(target, &mut priority);
+        if self.ranking.order.higher(target, &priority) {
+            self.heap.raise(slot, &self.ranking);
+        } else {
+            self.heap.lower(slot, &self.ranking);
+        }
+        priority
+    }
+
+    /// Returns the priority and sequence number that `slot` is ranked by.
+    fn rank(&self, slot: usize) -> (&P, u64) {
+        (self.heap.priority(slot), self.heap.sequence(slot))
+    }
+
+    /// Orders slots by when they pop, the first one as the least.
+    fn compare(&self, a: usize, b: usize) -> std::cmp::Ordering {
+        if self.ranking.higher(self.rank(a), self.rank(b)) {
+            std::cmp::Ordering::Less
+        } else if self.ranking.higher(self.rank(b), self.rank(a)) {
+            std::cmp::Ordering::Greater
+        } else {
+            std::cmp::Ordering::Equal
+        }
+    }
+
+    /// Removes the entry in `slot` and moves the last entry into its place.
+    fn remove_slot(&mut self, slot: usize) -> Entry<P, K, W> {
+        let (priority, sequence) = self.heap.remove(slot, &self.ranking);
+        self.take_slot(slot, priority, sequence)
+    }
+
+    /// Removes the entry in `slot` like [`remove_slot`](Self::remove_slot), without
+    /// restoring the heap. Callers must [`rebuild`](Self::rebuild) afterwards.
+    fn remove_slot_unordered(&mut self, slot: usize) -> Entry<P, K, W> {
+        let (priority, sequence) = self.heap.remove_unordered(slot);
+        self.take_slot(slot, priority, sequence)
+    }
+
+    /// Removes `slot` from the slots and the index once the heap no longer holds it.
+    fn take_slot(&mut self, slot: usize, priority: P, sequence: u64) -> Entry<P, K, W> {
+        let last = self.slots.len() - 1;
+        let removed = self.slots.swap_remove(slot);
+        move_index(&mut self.index, removed.hash, slot, None);
+        if let Some(moved) = self.slots.get(slot) {
+            move_index(&mut self.index, moved.hash, last, Some(slot));
+        }
+        Entry {
+            priority,
+            key: removed.key,
+            value: removed.value,
+            hash: removed.hash,
+            sequence,
+        }
+    }
+
+    /// Adds an entry for a key that is not in the map yet.
+    fn push_new(&mut self, hash: u64, priority: P, key: K, value: W) {
+        self.heap.check_priority(&priority);
+        let sequence = self.sequencer.next();
+        // Add the slot first, so that a panicking order leaves the map consistent.
+        self.push_slot(hash, key, value);
+        self.heap.push(priority, sequence, &self.ranking);
+    }
+
+    /// Inserts or replaces an entry without restoring the heap property.
+    ///
+    /// Callers must [`rebuild`](Self::rebuild) afterwards.
+    fn push_unordered(&mut self, priority: P, key: K, value: V) {
+        self.heap.check_priority(&priority);
+        let hash = self.hasher.hash_one(&key);
+        match self.find(hash, &key) {
+            Some(slot) => {
+                *self.slots[slot].value.value_mut() = value;
+                *self.heap.priority_mut(slot) = priority;
+            }
+            None => {
+                let sequence = self.sequencer.next();
+                self.push_entry(Entry {
+                    priority,
+                    key,
+                    value: W::store(value),
+                    hash,
+                    sequence,
+                });
+            }
         }
     }
 
-    pub fn len(&self) -> usize {
-        debug_assert_eq!(self.map.len(), self.heap.len());
-        self.map.len()
-    }
-
-    pub fn insert(&mut self, priority: P, key: K, value: V) {
-        match self.map.entry(key.clone()) {
-            hash_map::Entry::Occupied(e) => {
-                let position = *e.get();
-                let heap_element = &mut self.heap[*e.get()];
-                heap_element.value = value;
-                debug_assert!(&heap_element.key == e.key());
-                self.reprioritize_position(position, priority);
-            }
-            hash_map::Entry::Vacant(e) => {
-                let position = self.heap.len();
-                e.insert(position);
-                self.heap.push(Entry {
-                    priority,
-                    key,
-                    value,
-                });
-                self.swim_up(position);
-            }
+    /// Inserts or replaces an entry taken from another map like [`insert`](Self::insert),
+    /// keeping the stored value of a new key as it is.
+    fn insert_stored(&mut self, priority: P, key: K, value: W) {
+        let hash = self.hasher.hash_one(&key);
+        match self.find(hash, &key) {
+            Some(slot) => {
+                *self.slots[slot].value.value_mut() = value.into_value();
+                self.reprioritize_slot(slot, priority);
+            }
+            None => self.push_new(hash, priority, key, value),
+        }
+    }
+
+    /// Like [`insert_stored`](Self::insert_stored), without restoring the heap property.
+    ///
+    /// Callers must [`rebuild`](Self::rebuild) afterwards.
+    fn push_stored_unordered(&mut self, priority: P, key: K, value: W) {
+        self.heap.check_priority(&priority);
+        let hash = self.hasher.hash_one(&key);
+        match self.find(hash, &key) {
+            Some(slot) => {
+                *self.slots[slot].value.value_mut() = value.into_value();
+                *self.heap.priority_mut(slot) = priority;
+            }
+            None => {
+                let sequence = self.sequencer.next();
+                self.push_entry(Entry {
+                    priority,
+                    key,
+                    value,
+                    hash,
+                    sequence,
+                });
+            }
+        }
+    }
+
+    /// Adds an entry for a key that is not in the map yet at the end of the heap, without
+    /// restoring the heap property.
+    fn push_entry(&mut self, entry: Entry<P, K, W>) {
+        self.heap.check_priority(&entry.priority);
+        self.heap.push_unordered(entry.priority, entry.sequence);
+        self.push_slot(entry.hash, entry.key, entry.value);
+    }
+
+    /// Adds the slot and index entry for a key whose priority was just pushed to the heap.
+    fn push_slot(&mut self, hash: u64, key: K, value: W) {
+        let slot = self.slots.len();
+        self.slots.push(Slot { key, value, hash });
+        let slots = &self.slots;
+        self.index
+            .insert_unique(hash, to_idx(slot), |&other| slots[from_idx(other)].hash);
+    }
+
+    /// Removes all entries and returns them in arbitrary order.
+    fn take_entries(&mut self) -> Vec<Entry<P, K, W>> {
+        self.index.clear();
+        self.heap
+            .drain()
+            .zip(self.slots.drain(..))
+            .map(|((priority, sequence), slot)| Entry {
+                priority,
+                key: slot.key,
+                value: slot.value,
+                hash: slot.hash,
+                sequence,
+            })
+            .collect()
+    }
+
+    /// Fills the empty map with entries with pairwise distinct keys in `O(n)`.
+    ///
+    /// The cached hashes of the entries must come from a hasher equivalent to `self.hasher`.
+    fn fill(&mut self, entries: Vec<Entry<P, K, W>>) {
+        debug_assert!(self.is_empty());
+        self.reserve(entries.len());
+        for entry in entries {
+            self.push_entry(entry);
+        }
+        self.rebuild();
+    }
+
+    /// Builds a map in `O(n)` from entries with pairwise distinct keys.
+    ///
+    /// The cached hashes of the entries must come from a hasher equivalent to `hasher`.
+    fn from_unique_entries(
+        entries: Vec<Entry<P, K, W>>,
+        hasher: S,
+        ranking: Ranking<O>,
+        sequencer: Sequencer,
+    ) -> Self {
+        let mut this = Self::with_parts(entries.len(), hasher, ranking.order);
+        this.ranking.stable = ranking.stable;
+        this.sequencer = sequencer;
+        this.fill(entries);
+        this
+    }
+
+    /// Restores the heap property for the whole heap.
+    fn rebuild(&mut self) {
+        self.heap.rebuild(&self.ranking);
+    }
+}
+
+/// Access to the entry that pops last, for backends that track it, such as
+/// [`MinMaxHeap`].
+impl<P, K, V, S, O, B, W> PriorityMap<P, K, V, S, O, B, W>
+where
+    W: Storage<V>,
+    B: DoubleEndedBackend<P>,
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+{
+    /// Returns the value of the entry that pops last.
+    ///
+    /// Takes `O(1)` time with the default [`DAryHeap`] backend and with [`MinMaxHeap`].
+    pub fn peek_min(&self) -> Option<&V> {
+        Some(self.slots[self.heap.bottom(&self.ranking)?].value.value())
+    }
+
+    pub fn peek_min_full(&self) -> Option<(&P, &K, &V)> {
+        let slot = self.heap.bottom(&self.ranking)?;
+        let entry = &self.slots[slot];
+        Some((self.heap.priority(slot), &entry.key, entry.value.value()))
+    }
+
+    /// Removes the entry that pops last and returns its value.
+    ///
+    /// Takes `O(log n)` time with [`MinMaxHeap`], but `O(n)` with the default
+    /// [`DAryHeap`] backend, which scans its leaves for the next entry that pops last.
+    /// Use a [`MinMaxHeap`] to pop from both ends repeatedly.
+    pub fn pop_min(&mut self) -> Option<V> {
+        let slot = self.heap.bottom(&self.ranking)?;
+        Some(self.remove_slot(slot).value.into_value())
+    }
+
+    /// Like [`pop_min`](Self::pop_min), but also returns the priority and key. Takes
+    /// `O(n)` time with the default [`DAryHeap`] backend.
+    pub fn pop_min_full(&mut self) -> Option<(P, K, V)> {
+        let slot = self.heap.bottom(&self.ranking)?;
+        let entry = self.remove_slot(slot);
+        Some((entry.priority, entry.key, entry.value.into_value()))
+    }
+}
+
+/// Starvation diagnostics for maps that record insertion times, see [`Timestamped`].
+impl<P, K, V, S, O, B> PriorityMap<P, K, V, S, O, B, Timestamped<V>>
+where
+    B: HeapBackend<P>,
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+{
+    /// Returns when the entry was inserted.
+    pub fn inserted_at<Q>(&self, key: &Q) -> Option<Instant>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        Some(self.slots[self.slot(key)?].value.inserted())
+    }
+
+    /// Returns the entry that was inserted first, in `O(n)`.
+    ///
+    /// Entries inserted at the same instant are told apart by insertion order.
+    pub fn longest_waiting(&self) -> Option<(&P, &K, &V)> {
+        let (slot, entry) = self
+            .slots
+            .iter()
+            .enumerate()
+            .min_by_key(|&(slot, entry)| (entry.value.inserted(), self.heap.sequence(slot)))?;
+        Some((self.heap.priority(slot), &entry.key, entry.value.value()))
+    }
+
+    /// Yields the entries that have waited for at least `threshold` at `now`, in arbitrary
+    /// order.
+    pub fn starving(&self, now: Instant, threshold: Duration) -> Starving<'_, P, K, V, B> {
+        Starving {
+            heap: &self.heap,
+            inner: self.slots.iter().enumerate(),
+            now,
+            threshold,
+            priority: PhantomData,
+        }
+    }
+}
+
+impl<P, K, V, S, O, B, W> PriorityMap<P, K, V, S, O, B, W>
+where
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    K: Hash,
+    S: BuildHasher,
+{
+    /// Returns the slot of `key`.
+    fn slot<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.find(self.hasher.hash_one(key), key)
+    }
+
+    /// Returns the slot of `key`, given its hash.
+    fn find<Q>(&self, hash: u64, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        self.index
+            .find(hash, |&slot| self.slots[from_idx(slot)].key.borrow() == key)
+            .map(|&slot| from_idx(slot))
+    }
+}
+
+/// Changes the slot recorded for the key with `hash` from `old` to `new`, or removes it
+/// from the index if `new` is `None`.
+fn move_index(index: &mut HashTable<Idx>, hash: u64, old: usize, new: Option<usize>) {
+    let Ok(mut entry) = index.find_entry(hash, |&slot| from_idx(slot) == old) else {
+        panic!("slot must be indexed");
+    };
+    match new {
+        Some(new) => *entry.get_mut() = to_idx(new),
+        None => {
+            entry.remove();
+        }
+    }
+}
+
+impl<P, K, V, S, O, B, W> FromIterator<(P, K, V)> for PriorityMap<P, K, V, S, O, B, W>
+where
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    K: Eq + Hash,
+    S: BuildHasher + Default,
+    O: PriorityOrder<P> + Default,
+{
+    /// Collects all entries first and then builds the heap in `O(n)`.
+    ///
+    /// Later entries replace earlier ones with the same key, as with [`insert`](Self::insert).
+    fn from_iter<I: IntoIterator<Item = (P, K, V)>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        let (capacity, _) = iter.size_hint();
+        let mut map = Self::with_parts(capacity, S::default(), O::default());
+        for (priority, key, value) in iter {
+            map.push_unordered(priority, key, value);
+        }
+        map.rebuild();
+        map
+    }
+}
+
+impl<P, K, V, S, O, B, W> Extend<(P, K, V)> for PriorityMap<P, K, V, S, O, B, W>
+where
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+{
+    fn extend<I: IntoIterator<Item = (P, K, V)>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        let (additional, _) = iter.size_hint();
+        self.reserve(additional);
+        let total = self.heap.len() + additional;
+        let depth = (usize::BITS - total.leading_zeros()) as usize;
+        if additional.saturating_mul(depth) < total {
+            for (priority, key, value) in iter {
+                self.insert(priority, key, value);
+            }
+        } else {
+            for (priority, key, value) in iter {
+                self.push_unordered(priority, key, value);
+            }
+            self.rebuild();
+        }
+    }
+}
+
+impl<P, K, V, S, O, B, W> IntoIterator for PriorityMap<P, K, V, S, O, B, W>
+where
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    K: Hash,
+{
+    type Item = (P, K, V);
+    type IntoIter = IntoIter<P, K, V, B, W>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            inner: self.heap.into_nodes().zip(self.slots),
+            value: PhantomData,
+        }
+    }
+}
+
+impl<'a, P, K, V, S, O, B, W> IntoIterator for &'a PriorityMap<P, K, V, S, O, B, W>
+where
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    K: Hash,
+{
+    type Item = (&'a P, &'a K, &'a V);
+    type IntoIter = Iter<'a, P, K, V, B, W>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Iter {
+            heap: &self.heap,
+            inner: self.slots.iter().enumerate(),
+            value: PhantomData,
+        }
+    }
+}
+
+impl<'a, P, K, V, S, O, B, W> IntoIterator for &'a mut PriorityMap<P, K, V, S, O, B, W>
+where
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    K: Hash,
+{
+    type Item = (&'a P, &'a K, &'a mut V);
+    type IntoIter = IterMut<'a, P, K, V, B, W>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IterMut {
+            heap: &self.heap,
+            inner: self.slots.iter_mut().enumerate(),
+            value: PhantomData,
+        }
+    }
+}
+
+impl<P, K, V, S, O, B, W> Default for PriorityMap<P, K, V, S, O, B, W>
+where
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    K: Hash,
+    S: Default,
+    O: Default,
+{
+    fn default() -> Self {
+        Self {
+            heap: B::default(),
+            slots: vec![],
+            index: HashTable::default(),
+            hasher: S::default(),
+            ranking: Ranking::default(),
+            sequencer: Sequencer::default(),
+            value: PhantomData,
+        }
+    }
+}
+
+/// Maps are equal if they associate the same keys with the same priorities and values,
+/// regardless of the heap layout.
+impl<P, K, V, S, O, B, W> PartialEq for PriorityMap<P, K, V, S, O, B, W>
+where
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    P: PartialEq,
+    K: Eq + Hash,
+    V: PartialEq,
+    S: BuildHasher,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.slots.len() == other.slots.len()
+            && self.slots.iter().enumerate().all(|(i, slot)| {
+                other.slot(&slot.key).is_some_and(|j| {
+                    self.heap.priority(i) == other.heap.priority(j)
+                        && slot.value.value() == other.slots[j].value.value()
+                })
+            })
+    }
+}
+
+impl<P, K, V, S, O, B, W> Eq for PriorityMap<P, K, V, S, O, B, W>
+where
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    P: Eq,
+    K: Eq + Hash,
+    V: Eq,
+    S: BuildHasher,
+{
+}
+
+impl<P, K, V, S, O, Q, B, W> std::ops::Index<&Q> for PriorityMap<P, K, V, S, O, B, W>
+where
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    K: Eq + Hash + Borrow<Q>,
+    Q: Eq + Hash + ?Sized,
+    S: BuildHasher,
+{
+    type Output = V;
+
+    /// Returns the value for `key`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key is not present in the map.
+    fn index(&self, key: &Q) -> &V {
+        let slot = self.slot(key).expect("key not found in priority map");
+        self.slots[slot].value.value()
+    }
+}
+
+impl<P, K, V, S, O, Q, B, W> std::ops::IndexMut<&Q> for PriorityMap<P, K, V, S, O, B, W>
+where
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    K: Eq + Hash + Borrow<Q>,
+    Q: Eq + Hash + ?Sized,
+    S: BuildHasher,
+{
+    /// Returns the value for `key` mutably, without changing its priority.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key is not present in the map.
+    fn index_mut(&mut self, key: &Q) -> &mut V {
+        let slot = self.slot(key).expect("key not found in priority map");
+        self.slots[slot].value.value_mut()
+    }
+}
+
+impl<P, K, V, S, O, B, W> std::fmt::Debug for PriorityMap<P, K, V, S, O, B, W>
+where
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    P: std::fmt::Debug,
+    K: std::fmt::Debug + Hash,
+    V: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self).finish()
+    }
+}
+
+/// A key and value, stored apart from the heap so that sifting does not move them.
+#[derive(Clone, Debug)]
+struct Slot<K, V> {
+    key: K,
+    value: V,
+    /// The hash of `key` under the map's hasher, so index updates don't rehash the key.
+    hash: u64,
+}
+
+/// Merges an old and a new stored value with the `f` of
+/// [`merge_with`](PriorityMap::merge_with), keeping what the storage of the old value keeps
+/// alongside it.
+fn merge_stored<P, V, W, F>(f: &mut F, old_priority: P, old: W, priority: P, value: W) -> (P, W)
+where
+    W: Storage<V>,
+    F: FnMut(P, V, P, V) -> (P, V),
+{
+    let mut merged = None;
+    let value = old.map_value(|old_value| {
+        let (priority, value) = f(old_priority, old_value, priority, value.into_value());
+        merged = Some(priority);
+        value
+    });
+    (merged.expect("`map_value` calls `f`"), value)
+}
+
+/// An entry taken out of the map, or about to be added to it.
+struct Entry<P, K, V> {
+    priority: P,
+    key: K,
+    value: V,
+    hash: u64,
+    sequence: u64,
+}
+
+/// Hands out the insertion sequence numbers that break ties in stable mode.
+#[derive(Clone, Copy, Debug, Default)]
+struct Sequencer {
+    next: u64,
+}
+
+impl Sequencer {
+    fn next(&mut self) -> u64 {
+        let sequence = self.next;
+        self.next += 1;
+        sequence
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert() {
+        let mut map = PriorityMap::new();
+        assert_eq!(map.len(), 0);
+        assert_eq!(map.peek(), None);
+        assert_eq!(map.pop(), None);
+
+        map.insert(2, "b", "2");
+        map.insert(7, "g", "7");
+        map.insert(1, "a", "1");
+        map.insert(6, "f", "6");
+        map.insert(5, "e", "5");
+        map.insert(3, "c", "3");
+        map.insert(4, "d", "4");
+
+        assert_eq!(map.len(), 7);
+        assert_eq!(map.peek(), Some(&"7"));
+
+        assert_eq!(map.pop(), Some("7"));
+        assert_eq!(map.pop(), Some("6"));
+        assert_eq!(map.pop(), Some("5"));
+        assert_eq!(map.pop(), Some("4"));
+        assert_eq!(map.pop(), Some("3"));
+        assert_eq!(map.pop(), Some("2"));
+        assert_eq!(map.pop(), Some("1"));
+
+        assert_eq!(map.len(), 0);
+        assert_eq!(map.peek(), None);
+        assert_eq!(map.pop(), None);
+
+        assert_eq!(map.len(), 0);
+    }
+
+    #[test]
+    fn is_empty() {
+        let mut map = PriorityMap::new();
+        assert!(map.is_empty());
+        map.insert(1, "a", ());
+        assert!(!map.is_empty());
+        map.pop();
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn try_insert() {
+        let mut map = PriorityMap::new();
+        assert_eq!(map.try_insert(1, "a", "1"), Ok(()));
+        assert_eq!(map.try_insert(2, "b", "2"), Ok(()));
+        assert_eq!(
+            map.try_insert(3, "a", "3"),
+            Err(OccupiedError {
+                priority: 3,
+                key: "a",
+                value: "3"
+            })
+        );
+
+        assert_eq!(map.pop_full(), Some((2, "b", "2")));
+        assert_eq!(map.pop_full(), Some((1, "a", "1")));
+    }
+
+    #[test]
+    fn insert_if_higher() {
+        let mut map = PriorityMap::new();
+        assert!(map.insert_if_higher(2, "a", "2"));
+        assert!(map.insert_if_higher(3, "b", "3"));
+        assert!(!map.insert_if_higher(1, "a", "1"));
+        assert!(!map.insert_if_higher(2, "a", "2'"));
+        assert!(map.insert_if_higher(4, "a", "4"));
+
+        assert_eq!(map.pop_full(), Some((4, "a", "4")));
+        assert_eq!(map.pop_full(), Some((3, "b", "3")));
+    }
+
+    #[test]
+    fn upsert() {
+        let mut map = PriorityMap::new();
+        for key in ["a", "b", "a", "c", "a", "c"] {
+            map.upsert(
+                key,
+                || (1, 1),
+                |priority, value| {
+                    *priority += 1;
+                    *value *= 10;
+                },
+            );
+        }
+
+        assert_eq!(map.pop_full(), Some((3, "a", 100)));
+        assert_eq!(map.pop_full(), Some((2, "c", 10)));
+        assert_eq!(map.pop_full(), Some((1, "b", 1)));
+
+        map.insert(5, "a", 5);
+        map.insert(4, "b", 4);
+        map.upsert("a", || unreachable!(), |priority, _| *priority = 0);
+        assert_eq!(map.pop(), Some(4));
+    }
+
+    #[test]
+    fn clear() {
+        let mut map = PriorityMap::new();
+        map.insert(1, "a", "1");
+        map.insert(2, "b", "2");
+
+        map.clear();
+        assert_eq!(map.len(), 0);
+        assert!(!map.contains_key(&"a"));
+        assert_eq!(map.pop(), None);
+
+        map.insert(3, "a", "3");
+        assert_eq!(map.pop(), Some("3"));
+    }
+
+    #[test]
+    fn capacity_management() {
+        let mut map = PriorityMap::with_capacity(100);
+        assert!(map.capacity() >= 100);
+
+        for i in 0..10 {
+            map.insert(i, i, i);
+        }
+        map.reserve(1000);
+        assert!(map.capacity() >= 1010);
+
+        map.shrink_to_fit();
+        assert!(map.capacity() >= 10);
+        assert!(map.capacity() < 100);
+        assert_eq!(map.pop(), Some(9));
+    }
+
+    #[test]
+    fn default_clone_debug() {
+        let mut map: PriorityMap<i32, &str, &str> = Default::default();
+        assert_eq!(format!("{map:?}"), "[]");
+
+        map.insert(1, "a", "1");
+        let mut clone = map.clone();
+        clone.insert(2, "b", "2");
+
+        assert_eq!(format!("{map:?}"), r#"[(1, "a", "1")]"#);
+        assert_eq!(map.len(), 1);
+        assert_eq!(clone.pop(), Some("2"));
+        assert_eq!(clone.pop(), Some("1"));
+    }
+
+    #[test]
+    fn eq() {
+        let a: PriorityMap<_, _, _> = (0..10).map(|i| (i, i, i)).collect();
+        let b: PriorityMap<_, _, _> = (0..10).rev().map(|i| (i, i, i)).collect();
+        assert_eq!(a, b);
+
+        let mut c = b.clone();
+        c.reprioritize(&3, 30);
+        assert_ne!(a, c);
+
+        let mut d = b.clone();
+        d.replace_value(&3, 30);
+        assert_ne!(a, d);
+
+        let mut e = b.clone();
+        e.remove(&3);
+        assert_ne!(a, e);
+        e.insert(3, 11, 3);
+        assert_ne!(a, e);
+    }
+
+    #[test]
+    fn index() {
+        let mut map = PriorityMap::new();
+        map.insert(1, "a", 1);
+        map.insert(2, "b", 2);
+
+        assert_eq!(map[&"a"], 1);
+        map[&"a"] += 10;
+        assert_eq!(map[&"a"], 11);
+        assert_eq!(map.pop(), Some(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "key not found")]
+    fn index_missing() {
+        let map: PriorityMap<i32, &str, i32> = PriorityMap::new();
+        let _ = map[&"a"];
+    }
+
+    #[test]
+    fn borrowed_keys() {
+        let mut map = PriorityMap::new();
+        map.insert(1, "a".to_owned(), 1);
+        map.insert(2, "b".to_owned(), 2);
+
+        assert!(map.contains_key("a"));
+        assert_eq!(map.get("a"), Some(&1));
+        assert_eq!(map["b"], 2);
+        assert_eq!(map.reprioritize("a", 3), Some(1));
+        assert_eq!(map.remove("b"), Some(2));
+        assert_eq!(map.pop_full(), Some((3, "a".to_owned(), 1)));
+    }
+
+    #[test]
+    fn custom_hasher() {
+        type Hasher = std::hash::BuildHasherDefault<std::collections::hash_map::DefaultHasher>;
+
+        let mut map = PriorityMap::with_hasher(Hasher::default());
+        map.insert(1, "a", "1");
+        map.insert(2, "b", "2");
+        assert_eq!(map.get("a"), Some(&"1"));
+
+        let mut other = PriorityMap::with_capacity_and_hasher(10, Hasher::default());
+        other.insert(3, "c", "3");
+        map.append(&mut other);
+
+        let mut top: PriorityMap<_, _, _, Hasher> = map.split_off_by_priority(&2);
+        assert_eq!(top.pop(), Some("3"));
+        assert_eq!(top.pop(), Some("2"));
+        assert_eq!(map.pop(), Some("1"));
+
+        let collected: PriorityMap<_, _, _, Hasher> = [(1, "a", "1")].into_iter().collect();
+        assert_eq!(collected.len(), 1);
+    }
+
+    #[cfg(feature = "rustc-hash")]
+    #[test]
+    fn fx_priority_map() {
+        let mut map = FxPriorityMap::default();
+        map.insert(1, "a", "1");
+        map.insert(2, "b", "2");
+        assert_eq!(map.pop(), Some("2"));
+        assert_eq!(map.pop(), Some("1"));
+    }
+
+    #[cfg(feature = "ahash")]
+    #[test]
+    fn ahash_priority_map() {
+        let mut map = AHashPriorityMap::default();
+        map.insert(1, "a", "1");
+        map.insert(2, "b", "2");
+        assert_eq!(map.pop(), Some("2"));
+        assert_eq!(map.pop(), Some("1"));
+    }
+
+    #[test]
+    fn iter() {
+        let mut map = PriorityMap::new();
+        assert_eq!(map.iter().next(), None);
+
+        map.insert(1, "a", "1");
+        map.insert(3, "c", "3");
+        map.insert(2, "b", "2");
+
+        let iter = map.iter();
+        assert_eq!(iter.len(), 3);
+        let mut entries: Vec<_> = iter.collect();
+        entries.sort();
+        assert_eq!(
+            entries,
+            [(&1, &"a", &"1"), (&2, &"b", &"2"), (&3, &"c", &"3")]
+        );
+        assert_eq!(map.len(), 3);
+    }
+
+    #[test]
+    fn iter_mut() {
+        let mut map = PriorityMap::new();
+        map.insert(1, "a", 10);
+        map.insert(3, "c", 30);
+        map.insert(2, "b", 20);
+
+        for (priority, _, value) in map.iter_mut() {
+            *value += priority;
+        }
+
+        assert_eq!(map.pop(), Some(33));
+        assert_eq!(map.pop(), Some(22));
+        assert_eq!(map.pop(), Some(11));
+    }
+
+    #[test]
+    fn into_iter() {
+        let mut map = PriorityMap::new();
+        map.insert(1, "a", 10);
+        map.insert(3, "c", 30);
+        map.insert(2, "b", 20);
+
+        for (_, _, value) in &mut map {
+            *value += 1;
+        }
+        let mut sum = 0;
+        for (_, _, value) in &map {
+            sum += value;
+        }
+        assert_eq!(sum, 63);
+
+        let mut entries: Vec<_> = map.into_iter().collect();
+        entries.sort();
+        assert_eq!(entries, [(1, "a", 11), (2, "b", 21), (3, "c", 31)]);
+    }
+
+    #[test]
+    fn keys() {
+        let mut map = PriorityMap::new();
+        map.insert(1, "a", "1");
+        map.insert(3, "c", "3");
+        map.insert(2, "b", "2");
+
+        let mut keys: Vec<_> = map.keys().copied().collect();
+        keys.sort();
+        assert_eq!(keys, ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn values() {
+        let mut map = PriorityMap::new();
+        map.insert(1, "a", 10);
+        map.insert(3, "c", 30);
+        map.insert(2, "b", 20);
+
+        assert_eq!(map.values().len(), 3);
+        assert_eq!(map.values().sum::<i32>(), 60);
+    }
+
+    #[test]
+    fn priorities() {
+        let mut map = PriorityMap::new();
+        map.insert(1, "a", "1");
+        map.insert(3, "c", "3");
+        map.insert(2, "b", "2");
+
+        assert_eq!(map.priorities().min(), Some(&1));
+        assert_eq!(map.priorities().sum::<i32>(), 6);
+    }
+
+    #[test]
+    fn into_keys_and_values() {
+        let mut map = PriorityMap::new();
+        map.insert(1, "a", 10);
+        map.insert(3, "c", 30);
+        map.insert(2, "b", 20);
+
+        let mut keys: Vec<_> = map.into_keys().collect();
+        keys.sort();
+        assert_eq!(keys, ["a", "b", "c"]);
+
+        let mut map = PriorityMap::new();
+        map.insert(1, "a", 10);
+        map.insert(3, "c", 30);
+        map.insert(2, "b", 20);
+
+        let mut values: Vec<_> = map.into_values().collect();
+        values.sort();
+        assert_eq!(values, [10, 20, 30]);
+    }
+
+    #[test]
+    fn iter_sorted() {
+        let mut map = PriorityMap::new();
+        assert_eq!(map.iter_sorted().next(), None);
+
+        map.insert(2, "b", "2");
+        map.insert(7, "g", "7");
+        map.insert(1, "a", "1");
+        map.insert(6, "f", "6");
+        map.insert(5, "e", "5");
+        map.insert(3, "c", "3");
+        map.insert(4, "d", "4");
+
+        let keys: Vec<_> = map.iter_sorted().map(|(_, key, _)| *key).collect();
+        assert_eq!(keys, ["g", "f", "e", "d", "c", "b", "a"]);
+
+        let top: Vec<_> = map.iter_sorted().take(2).map(|(p, _, _)| *p).collect();
+        assert_eq!(top, [7, 6]);
+        assert_eq!(map.len(), 7);
+    }
+
+    #[test]
+    fn peek_nth() {
+        let mut map: PriorityMap<_, _, _> = (0..100).map(|i| (i * 37 % 100, i, ())).collect();
+        assert_eq!(map.peek_nth(100), None);
+        for n in [0, 1, 9, 50, 99] {
+            let (priority, key, _) = map.peek_nth(n).unwrap();
+            assert_eq!(*priority, 99 - n);
+            assert_eq!(key * 37 % 100, 99 - n);
+        }
+        map.set_stable(true);
+        map.insert(90, 1000, ());
+        assert_eq!(map.peek_nth(9).map(|(_, key, _)| *key), Some(70));
+        assert_eq!(map.peek_nth(10).map(|(_, key, _)| *key), Some(1000));
+        assert_eq!(map.peek_nth(80).map(|(p, _, _)| *p), Some(20));
+        assert_eq!(map.len(), 101);
+    }
+
+    #[test]
+    fn priority_quantile() {
+        let mut map = PriorityMap::new_min();
+        assert_eq!(map.priority_quantile(0.5), None);
+        map.extend((0..101).map(|i| (i * 37 % 101, i, ())));
+        assert_eq!(map.priority_quantile(0.0), Some(&100));
+        assert_eq!(map.priority_quantile(0.5), Some(&50));
+        assert_eq!(map.priority_quantile(0.9), Some(&10));
+        assert_eq!(map.priority_quantile(1.0), Some(&0));
+    }
+
+    #[test]
+    fn priority_histogram() {
+        let map: PriorityMap<_, _, _> = (0..100).map(|i| (i, i, ())).collect();
+        assert_eq!(map.priority_histogram(&[]), [100]);
+        assert_eq!(
+            map.priority_histogram(&[10, 50, 50, 90]),
+            [10, 40, 0, 40, 10]
+        );
+        assert_eq!(map.priority_histogram(&[-5, 200]), [0, 100, 0]);
+    }
+
+    #[test]
+    fn peek_top_n() {
+        let mut map = PriorityMap::new();
+        assert!(map.peek_top_n(3).is_empty());
+
+        for i in 0..10 {
+            map.insert(i, i, i);
+        }
+
+        assert_eq!(
+            map.peek_top_n(3),
+            [(&9, &9, &9), (&8, &8, &8), (&7, &7, &7)]
+        );
+        assert_eq!(map.peek_top_n(0), []);
+        assert_eq!(map.peek_top_n(20).len(), 10);
+        assert_eq!(map.len(), 10);
+    }
+
+    #[test]
+    fn drain() {
+        let mut map = PriorityMap::new();
+        map.insert(1, "a", "1");
+        map.insert(3, "c", "3");
+        map.insert(2, "b", "2");
+
+        let mut entries: Vec<_> = map.drain().collect();
+        entries.sort();
+        assert_eq!(entries, [(1, "a", "1"), (2, "b", "2"), (3, "c", "3")]);
+        assert_eq!(map.len(), 0);
+        assert_eq!(map.pop(), None);
+
+        map.insert(4, "a", "4");
+        assert_eq!(map.pop(), Some("4"));
+    }
+
+    #[test]
+    fn into_sorted_vec() {
+        let mut map = PriorityMap::new();
+        map.insert(2, "b", 2);
+        map.insert(7, "g", 7);
+        map.insert(1, "a", 1);
+        map.insert(6, "f", 6);
+        map.insert(5, "e", 5);
+        map.insert(3, "c", 3);
+        map.insert(4, "d", 4);
+
+        assert_eq!(
+            map.into_sorted_vec(),
+            [
+                (7, "g", 7),
+                (6, "f", 6),
+                (5, "e", 5),
+                (4, "d", 4),
+                (3, "c", 3),
+                (2, "b", 2),
+                (1, "a", 1)
+            ]
+        );
+        assert!(PriorityMap::<u8, u8, u8>::new()
+            .into_sorted_vec()
+            .is_empty());
+    }
+
+    #[test]
+    fn drain_sorted() {
+        let mut map = PriorityMap::new();
+        map.insert(2, "b", "2");
+        map.insert(4, "d", "4");
+        map.insert(1, "a", "1");
+        map.insert(3, "c", "3");
+
+        let mut drain = map.drain_sorted();
+        assert_eq!(drain.len(), 4);
+        assert_eq!(drain.next(), Some((4, "d", "4")));
+        assert_eq!(drain.next(), Some((3, "c", "3")));
+        drop(drain);
+
+        assert_eq!(map.len(), 0);
+        assert_eq!(map.pop(), None);
+    }
+
+    #[test]
+    fn from_iter() {
+        let mut map: PriorityMap<_, _, _> = [(1, "a", "1"), (3, "c", "3"), (2, "a", "2")]
+            .into_iter()
+            .collect();
+
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.pop(), Some("3"));
+        assert_eq!(map.pop(), Some("2"));
+        assert_eq!(map.pop(), None);
+    }
+
+    #[test]
+    fn extend() {
+        let mut map = PriorityMap::new();
+        map.insert(2, "b", "2");
+        map.extend([(1, "a", "1"), (3, "b", "3")]);
+
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.pop(), Some("3"));
+        assert_eq!(map.pop(), Some("1"));
+    }
+
+    #[test]
+    fn retain() {
+        let mut map = PriorityMap::new();
+        for i in 0..10 {
+            map.insert(i, i, i);
+        }
+
+        map.retain(|priority, _, value| {
+            *value *= 10;
+            priority % 3 != 0
+        });
+
+        assert_eq!(map.len(), 6);
+        assert_eq!(map.remove(&3), None);
+        assert_eq!(map.remove(&4), Some(40));
+        assert_eq!(map.pop(), Some(80));
+        assert_eq!(map.pop(), Some(70));
+        assert_eq!(map.pop(), Some(50));
+        assert_eq!(map.pop(), Some(20));
+        assert_eq!(map.pop(), Some(10));
+        assert_eq!(map.pop(), None);
+    }
+
+    #[test]
+    fn extract_if() {
+        let mut map = PriorityMap::new();
+        for i in 0..10 {
+            map.insert(i, i, i);
+        }
+
+        let mut extracted: Vec<_> = map.extract_if(|_, key, _| key % 2 == 0).collect();
+        extracted.sort();
+        assert_eq!(
+            extracted,
+            [(0, 0, 0), (2, 2, 2), (4, 4, 4), (6, 6, 6), (8, 8, 8)]
+        );
+
+        assert_eq!(map.len(), 5);
+        assert_eq!(map.remove(&4), None);
+        assert_eq!(map.pop(), Some(9));
+        assert_eq!(map.pop(), Some(7));
+        assert_eq!(map.pop(), Some(5));
+        assert_eq!(map.pop(), Some(3));
+        assert_eq!(map.pop(), Some(1));
+        assert_eq!(map.pop(), None);
+    }
+
+    #[test]
+    fn extract_if_early_drop() {
+        let mut map = PriorityMap::new();
+        for i in 0..10 {
+            map.insert(i, i, i);
+        }
+
+        let first = map.extract_if(|priority, _, _| *priority < 5).next();
+        assert!(first.is_some());
+
+        assert_eq!(map.len(), 9);
+        let values: Vec<_> = map.drain_sorted().map(|(_, _, value)| value).collect();
+        assert_eq!(values.len(), 9);
+        assert!(values.windows(2).all(|pair| pair[0] > pair[1]));
+    }
+
+    #[test]
+    fn contains_key() {
+        let mut map = PriorityMap::new();
+        assert!(!map.contains_key(&"a"));
+
+        map.insert(1, "a", "1");
+        assert!(map.contains_key(&"a"));
+        assert!(!map.contains_key(&"b"));
+
+        map.remove(&"a");
+        assert!(!map.contains_key(&"a"));
+    }
+
+    #[test]
+    fn get() {
+        let mut map = PriorityMap::new();
+        assert_eq!(map.get(&"a"), None);
+
+        map.insert(1, "a", "1");
+        map.insert(2, "b", "2");
+
+        assert_eq!(map.get(&"a"), Some(&"1"));
+        assert_eq!(map.get(&"b"), Some(&"2"));
+        assert_eq!(map.get(&"c"), None);
+    }
+
+    #[test]
+    fn get_mut() {
+        let mut map = PriorityMap::new();
+        map.insert(1, "a", 1);
+        map.insert(2, "b", 2);
+
+        *map.get_mut(&"a").unwrap() += 10;
+        assert!(map.get_mut(&"c").is_none());
+
+        assert_eq!(map.get(&"a"), Some(&11));
+        assert_eq!(map.pop(), Some(2));
+        assert_eq!(map.pop(), Some(11));
+    }
+
+    #[test]
+    fn get_priority() {
+        let mut map = PriorityMap::new();
+        map.insert(1, "a", "1");
+        map.insert(2, "b", "2");
+        map.reprioritize(&"a", 3);
+
+        assert_eq!(map.get_priority(&"a"), Some(&3));
+        assert_eq!(map.get_priority(&"b"), Some(&2));
+        assert_eq!(map.get_priority(&"c"), None);
+    }
+
+    #[test]
+    fn get_full() {
+        let mut map = PriorityMap::new();
+        map.insert(1, "a", "1");
+        map.insert(2, "b", "2");
+
+        assert_eq!(map.get_full(&"a"), Some((&1, &"a", &"1")));
+        assert_eq!(map.get_full(&"b"), Some((&2, &"b", &"2")));
+        assert_eq!(map.get_full(&"c"), None);
+    }
+
+    #[test]
+    fn peek_full() {
+        let mut map = PriorityMap::new();
+        assert_eq!(map.peek_full(), None);
+
+        map.insert(1, "a", "1");
+        map.insert(2, "b", "2");
+
+        assert_eq!(map.peek_full(), Some((&2, &"b", &"2")));
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn pop_full() {
+        let mut map = PriorityMap::new();
+        assert_eq!(map.pop_full(), None);
+
+        map.insert(1, "a", "1");
+        map.insert(2, "b", "2");
+
+        assert_eq!(map.pop_full(), Some((2, "b", "2")));
+        assert_eq!(map.pop_full(), Some((1, "a", "1")));
+        assert_eq!(map.pop_full(), None);
+    }
+
+    #[test]
+    fn peek_mut() {
+        let mut map = PriorityMap::new();
+        assert!(map.peek_mut().is_none());
+
+        map.insert(1, "a", 1);
+        map.insert(2, "b", 2);
+        map.insert(3, "c", 3);
+
+        {
+            let mut top = map.peek_mut().unwrap();
+            assert_eq!(top.key(), &"c");
+            *top += 30;
+            *top.priority_mut() = 0;
+        }
+        assert_eq!(map.peek_full(), Some((&2, &"b", &2)));
+
+        let top = map.peek_mut().unwrap();
+        assert_eq!(PeekMut::pop(top), (2, "b", 2));
+
+        assert_eq!(map.pop(), Some(1));
+        assert_eq!(map.pop(), Some(33));
+        assert_eq!(map.pop(), None);
+    }
+
+    #[test]
+    fn pop_if() {
+        let mut map = PriorityMap::new();
+        assert_eq!(map.pop_if(|_, _, _| true), None);
+
+        map.insert(1, "a", "1");
+        map.insert(2, "b", "2");
+
+        assert_eq!(map.pop_if(|priority, _, _| *priority > 2), None);
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.pop_if(|_, key, _| *key == "b"), Some((2, "b", "2")));
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn pop_while() {
+        let mut map = PriorityMap::new();
+        for i in 0..10 {
+            map.insert(i, i, i);
+        }
+
+        let popped: Vec<_> = map.pop_while(|priority, _, _| *priority >= 7).collect();
+        assert_eq!(popped, [(9, 9, 9), (8, 8, 8), (7, 7, 7)]);
+        assert_eq!(map.len(), 7);
+        assert_eq!(map.pop(), Some(6));
+    }
+
+    #[test]
+    fn pop_batch() {
+        for (len, n) in [(0, 3), (10, 0), (10, 2), (10, 7), (10, 20), (1000, 10)] {
+            let mut map = PriorityMap::new();
+            for i in 0..len {
+                map.insert(i, i, i);
+            }
+
+            let batch = map.pop_batch(n);
+            let expected: Vec<_> = (0..len).rev().take(n).map(|i| (i, i, i)).collect();
+            assert_eq!(batch, expected);
+            assert_eq!(map.len(), len - batch.len());
+
+            let rest: Vec<_> = map
+                .drain_sorted()
+                .map(|(priority, _, _)| priority)
+                .collect();
+            let expected: Vec<_> = (0..len).rev().skip(n).collect();
+            assert_eq!(rest, expected);
+        }
+    }
+
+    #[test]
+    fn drain_top() {
+        let mut map = PriorityMap::new();
+        for i in 0..10 {
+            map.insert(i, i, i);
+        }
+
+        let top: Vec<_> = map.drain_top(3).collect();
+        assert_eq!(top, [(9, 9, 9), (8, 8, 8), (7, 7, 7)]);
+        assert_eq!(map.len(), 7);
+
+        let mut drain = map.drain_top(3);
+        assert_eq!(drain.len(), 3);
+        assert_eq!(drain.next(), Some((6, 6, 6)));
+        drop(drain);
+        assert_eq!(map.len(), 4);
+        assert_eq!(map.pop(), Some(3));
+
+        assert_eq!(map.drain_top(10).count(), 3);
+        assert_eq!(map.len(), 0);
+    }
+
+    #[test]
+    fn truncate_top() {
+        let mut map = PriorityMap::new();
+        for i in [5, 2, 8, 0, 9, 1, 7, 3, 6, 4] {
+            map.insert(i, i, i);
         }
-    }
 
-    pub fn peek(&self) -> Option<&V> {
-        let entry = self.heap.get(0)?;
-        Some(&entry.value)
+        map.truncate_top(20);
+        assert_eq!(map.len(), 10);
+
+        map.truncate_top(3);
+        assert_eq!(map.len(), 3);
+        assert!(!map.contains_key(&6));
+        assert_eq!(map.pop_full(), Some((9, 9, 9)));
+        assert_eq!(map.pop_full(), Some((8, 8, 8)));
+        assert_eq!(map.pop_full(), Some((7, 7, 7)));
+
+        map.insert(1, 1, 1);
+        map.truncate_top(0);
+        assert_eq!(map.len(), 0);
+        assert!(!map.contains_key(&1));
     }
 
-    pub fn pop(&mut self) -> Option<V> {
-        if self.heap.is_empty() {
-            debug_assert!(self.map.is_empty());
-            return None;
+    #[test]
+    fn split_off_by_priority() {
+        let mut map = PriorityMap::new();
+        for i in [5, 2, 8, 0, 9, 1, 7, 3, 6, 4] {
+            map.insert(i, i, i);
         }
-        let entry = self.heap.swap_remove(0);
-        let position = self.map.remove(&entry.key);
-        debug_assert_eq!(position, Some(0));
 
-        if !self.heap.is_empty() {
-            self.sink_down(0);
+        let mut urgent = map.split_off_by_priority(&7);
+        assert_eq!(urgent.len(), 3);
+        assert_eq!(map.len(), 7);
+        assert!(!map.contains_key(&7));
+        assert_eq!(urgent.pop(), Some(9));
+        assert_eq!(urgent.pop(), Some(8));
+        assert_eq!(urgent.pop(), Some(7));
+        assert_eq!(map.pop(), Some(6));
+
+        assert_eq!(map.split_off_by_priority(&100).len(), 0);
+        assert_eq!(map.len(), 6);
+    }
+
+    #[test]
+    fn partition() {
+        let mut map = PriorityMap::new();
+        for i in 0..10 {
+            map.insert(i, i, i);
         }
 
-        Some(entry.value)
+        let (mut even, mut odd) = map.partition(|_, key, _| key % 2 == 0);
+        assert_eq!(even.len(), 5);
+        assert_eq!(odd.len(), 5);
+        assert!(even.contains_key(&4));
+        assert!(!odd.contains_key(&4));
+        assert_eq!(even.pop(), Some(8));
+        assert_eq!(odd.pop(), Some(9));
+        assert_eq!(odd.remove(&3), Some(3));
+        assert_eq!(odd.pop(), Some(7));
     }
 
-    pub fn remove(&mut self, key: &K) -> Option<V> {
-        let position = self.map.remove(&key)?;
-        let entry = self.heap.swap_remove(position);
-        debug_assert!(key == &entry.key);
+    #[test]
+    fn append() {
+        for (self_len, other_len) in [(0, 5), (5, 0), (100, 3), (10, 10), (3, 100)] {
+            let mut map = PriorityMap::new();
+            for i in 0..self_len {
+                map.insert(i, i, 0);
+            }
+            let mut other = PriorityMap::new();
+            for i in 0..other_len {
+                other.insert(2 * i, i, 1);
+            }
+
+            map.append(&mut other);
+            assert_eq!(other.len(), 0);
+            assert_eq!(map.len(), self_len.max(other_len));
+            for i in 0..other_len {
+                assert_eq!(map.get_full(&i), Some((&(2 * i), &i, &1)));
+            }
 
-        if self.heap.len() > position {
-            self.sink_down(position);
+            let priorities: Vec<_> = map
+                .drain_sorted()
+                .map(|(priority, _, _)| priority)
+                .collect();
+            assert!(priorities.windows(2).all(|pair| pair[0] >= pair[1]));
         }
-        Some(entry.value)
     }
 
-    pub fn reprioritize(&mut self, key: &K, priority: P) -> Option<P> {
-        let position = *self.map.get(&key)?;
-        self.reprioritize_position(position, priority)
-    }
+    #[test]
+    fn merge_with() {
+        for (self_len, other_len) in [(0, 5), (5, 0), (100, 3), (10, 10), (3, 100)] {
+            let mut map = PriorityMap::new();
+            for i in 0..self_len {
+                map.insert(i, i, 1);
+            }
+            let mut other = PriorityMap::new();
+            for i in 0..other_len {
+                other.insert(i, i, 1);
+            }
 
-    fn reprioritize_position(&mut self, position: usize, mut priority: P) -> Option<P> {
-        let target = &mut self.heap[position].priority;
-        std::mem::swap(target, &mut priority);
-        if *target > priority {
-            self.swim_up(position);
-        } else {
-            self.sink_down(position);
+            map.merge_with(other, |old_p, old_v, new_p, new_v| {
+                (old_p + new_p, old_v + new_v)
+            });
+            assert_eq!(map.len(), self_len.max(other_len));
+            for i in 0..self_len.max(other_len) {
+                let expected = if i < self_len.min(other_len) {
+                    (2 * i, 2)
+                } else {
+                    (i, 1)
+                };
+                assert_eq!(map.get_full(&i), Some((&expected.0, &i, &expected.1)));
+            }
+
+            let priorities: Vec<_> = map
+                .drain_sorted()
+                .map(|(priority, _, _)| priority)
+                .collect();
+            assert!(priorities.windows(2).all(|pair| pair[0] >= pair[1]));
         }
-        Some(priority)
     }
 
-    fn swim_up(&mut self, position: usize) -> usize {
-        self.sift(position, Self::lesser_parent)
-    }
+    #[test]
+    fn replace_value() {
+        let mut map = PriorityMap::new();
+        map.insert(1, "a", "1");
+        map.insert(2, "b", "2");
 
-    fn sink_down(&mut self, position: usize) -> usize {
-        self.sift(position, Self::greater_child)
+        assert_eq!(map.replace_value(&"a", "x"), Some("1"));
+        assert_eq!(map.replace_value(&"c", "y"), None);
+        assert!(!map.contains_key(&"c"));
+
+        assert_eq!(map.pop_full(), Some((2, "b", "2")));
+        assert_eq!(map.pop_full(), Some((1, "a", "x")));
     }
 
-    fn sift<F: Fn(&Self, usize) -> Option<usize>>(&mut self, mut position: usize, f: F) -> usize {
-        let original_key = self.heap[position].key.clone();
-        while let Some(other) = f(self, position) {
-            let other_key = self.heap[other].key.clone();
-            self.heap.swap(other, position);
-            debug_assert_eq!(self.map[&other_key], other);
-            self.map.insert(other_key.clone(), position);
+    #[test]
+    fn reprioritize() {
+        let mut map = PriorityMap::new();
+        map.insert(1, "a", "1");
+        map.insert(2, "b", "2");
+        map.insert(3, "c", "3");
+
+        map.reprioritize(&"b", 200);
 
-            position = other;
-        }
-        self.map.insert(original_key, position);
-        position
+        assert_eq!(map.pop(), Some("2"));
+        assert_eq!(map.pop(), Some("3"));
+        assert_eq!(map.pop(), Some("1"));
     }
 
-    fn lesser_parent(&self, position: usize) -> Option<usize> {
-        if position == 0 {
-            return None;
-        }
+    #[test]
+    fn reprioritize_by() {
+        let mut map = PriorityMap::new();
+        map.insert(1, "a", "1");
+        map.insert(2, "b", "2");
+        map.insert(3, "c", "3");
 
-        let parent = (position - 1) / 2;
-        (self.heap[parent].priority < self.heap[position].priority).then_some(parent)
-    }
+        assert!(map.reprioritize_by(&"a", |priority| *priority += 10));
+        assert!(map.reprioritize_by(&"c", |priority| *priority -= 3));
+        assert!(!map.reprioritize_by(&"d", |_| unreachable!()));
 
-    fn greater_child(&self, position: usize) -> Option<usize> {
-        self.max_child(position)
-            .filter(|child| self.heap[*child].priority > self.heap[position].priority)
+        assert_eq!(map.pop_full(), Some((11, "a", "1")));
+        assert_eq!(map.pop_full(), Some((2, "b", "2")));
+        assert_eq!(map.pop_full(), Some((0, "c", "3")));
     }
 
-    fn max_child(&self, position: usize) -> Option<usize> {
-        let left = 2 * position + 1;
-        if left < self.heap.len() {
-            let right = 2 * position + 2;
-            if right < self.heap.len() {
-                if self.heap[left].priority < self.heap[right].priority {
-                    return Some(right);
-                }
-            }
-            return Some(left);
-        }
-        None
-    }
-}
+    #[test]
+    fn increase_and_decrease_priority() {
+        let mut map = PriorityMap::new();
+        map.insert(1.0, "a", "1");
+        map.insert(2.0, "b", "2");
+        map.insert(3.0, "c", "3");
 
-#[derive(Debug)]
-struct Entry<P, K, V> {
-    priority: P,
-    key: K,
-    value: V,
-}
+        assert!(map.increase_priority(&"a", 2.5));
+        assert!(map.decrease_priority(&"c", 3.0));
+        assert!(!map.increase_priority(&"d", 1.0));
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        assert_eq!(map.pop_full(), Some((3.5, "a", "1")));
+        assert_eq!(map.pop_full(), Some((2.0, "b", "2")));
+        assert_eq!(map.pop_full(), Some((0.0, "c", "3")));
+    }
 
     #[test]
-    fn insert() {
+    fn reprioritize_if_higher() {
         let mut map = PriorityMap::new();
-        assert_eq!(map.len(), 0);
-        assert_eq!(map.peek(), None);
-        assert_eq!(map.pop(), None);
-
-        map.insert(2, "b", "2");
-        map.insert(7, "g", "7");
         map.insert(1, "a", "1");
-        map.insert(6, "f", "6");
-        map.insert(5, "e", "5");
-        map.insert(3, "c", "3");
-        map.insert(4, "d", "4");
+        map.insert(2, "b", "2");
 
-        assert_eq!(map.len(), 7);
-        assert_eq!(map.peek(), Some(&"7"));
+        assert!(!map.reprioritize_if_higher(&"b", 1));
+        assert!(!map.reprioritize_if_higher(&"b", 2));
+        assert!(map.reprioritize_if_higher(&"a", 3));
+        assert!(!map.reprioritize_if_higher(&"c", 5));
+        assert!(!map.contains_key(&"c"));
 
-        assert_eq!(map.pop(), Some("7"));
-        assert_eq!(map.pop(), Some("6"));
-        assert_eq!(map.pop(), Some("5"));
-        assert_eq!(map.pop(), Some("4"));
-        assert_eq!(map.pop(), Some("3"));
-        assert_eq!(map.pop(), Some("2"));
-        assert_eq!(map.pop(), Some("1"));
+        assert_eq!(map.pop_full(), Some((3, "a", "1")));
+        assert_eq!(map.pop_full(), Some((2, "b", "2")));
+    }
 
-        assert_eq!(map.len(), 0);
-        assert_eq!(map.peek(), None);
-        assert_eq!(map.pop(), None);
+    #[test]
+    fn reprioritize_all() {
+        let mut map = PriorityMap::new();
+        for i in 0..10 {
+            map.insert(i, i, i);
+        }
 
-        assert_eq!(map.len(), 0);
+        map.reprioritize_all(|key, _, priority| *priority = (key * 7) % 10);
+
+        let keys: Vec<_> = map.drain_sorted().map(|(_, key, _)| key).collect();
+        assert_eq!(keys, [7, 4, 1, 8, 5, 2, 9, 6, 3, 0]);
     }
 
     #[test]
-    fn reprioritize() {
+    fn swap_priorities() {
         let mut map = PriorityMap::new();
         map.insert(1, "a", "1");
         map.insert(2, "b", "2");
         map.insert(3, "c", "3");
 
-        map.reprioritize(&"b", 200);
+        assert!(map.swap_priorities(&"a", &"c"));
+        assert!(map.swap_priorities(&"b", &"b"));
+        assert!(!map.swap_priorities(&"a", &"d"));
 
-        assert_eq!(map.pop(), Some("2"));
-        assert_eq!(map.pop(), Some("3"));
-        assert_eq!(map.pop(), Some("1"));
+        assert_eq!(map.get_priority(&"a"), Some(&3));
+        assert_eq!(map.remove(&"b"), Some("2"));
+        assert_eq!(map.pop_full(), Some((3, "a", "1")));
+        assert_eq!(map.pop_full(), Some((1, "c", "3")));
     }
 
     #[test]
@@ -219,6 +2677,97 @@ mod tests {
         assert_eq!(map.pop(), Some("1"));
     }
 
+    #[test]
+    fn insert_returns_previous() {
+        let mut map = PriorityMap::new();
+        assert_eq!(map.insert(1, "a", "1"), None);
+        assert_eq!(map.insert(2, "b", "2"), None);
+        assert_eq!(map.insert(3, "a", "3"), Some((1, "1")));
+        assert_eq!(map.insert(0, "a", "0"), Some((3, "3")));
+
+        assert_eq!(map.pop_full(), Some((2, "b", "2")));
+        assert_eq!(map.pop_full(), Some((0, "a", "0")));
+    }
+
+    #[test]
+    fn remove_full() {
+        let mut map = PriorityMap::new();
+        map.insert(1, "a", "1");
+        map.insert(2, "b", "2");
+
+        assert_eq!(map.remove_full(&"b"), Some((2, "2")));
+        assert_eq!(map.remove_full(&"b"), None);
+        assert_eq!(map.pop_full(), Some((1, "a", "1")));
+    }
+
+    #[test]
+    fn remove_batch() {
+        for (len, keys) in [
+            (10, vec![3, 20]),
+            (10, (0..10).step_by(2).collect()),
+            (0, vec![1]),
+        ] {
+            let mut map = PriorityMap::new();
+            for i in 0..len {
+                map.insert(i, i, i);
+            }
+
+            let expected_removed = keys.iter().filter(|key| **key < len).count();
+            assert_eq!(map.remove_batch(keys.clone()), expected_removed);
+            assert_eq!(map.len(), len - expected_removed);
+            for key in &keys {
+                assert!(!map.contains_key(key));
+            }
+
+            let rest: Vec<_> = map.drain_sorted().map(|(_, key, _)| key).collect();
+            let expected: Vec<_> = (0..len).rev().filter(|key| !keys.contains(key)).collect();
+            assert_eq!(rest, expected);
+        }
+    }
+
+    #[test]
+    fn remove_moves_last_entry_up() {
+        let mut map = PriorityMap::new();
+        map.insert(10, "a", "10");
+        map.insert(1, "b", "1");
+        map.insert(9, "c", "9");
+        map.insert(0, "d", "0");
+        map.insert(0, "e", "0");
+        map.insert(8, "f", "8");
+        map.insert(7, "g", "7");
+
+        assert_eq!(map.remove(&"d"), Some("0"));
+        assert_eq!(map.pop(), Some("10"));
+        assert_eq!(map.pop(), Some("9"));
+        assert_eq!(map.pop(), Some("8"));
+        assert_eq!(map.pop(), Some("7"));
+    }
+
+    #[test]
+    fn values_stay_with_keys() {
+        let mut map = PriorityMap::new();
+        for i in 0..50 {
+            map.insert(i * 7 % 50, i, i.to_string());
+        }
+        for i in (0..50).step_by(3) {
+            assert_eq!(map.remove(&i), Some(i.to_string()));
+        }
+        for i in (1..50).step_by(4) {
+            map.reprioritize(&i, 100 + i);
+        }
+        map.swap_priorities(&1, &2);
+        for (_, key, value) in &map {
+            assert_eq!(value, &key.to_string());
+        }
+        let sorted = map.clone().into_sorted_vec();
+        assert_eq!(sorted.len(), 33);
+        assert!(sorted.windows(2).all(|pair| pair[0].0 >= pair[1].0));
+        assert!(sorted
+            .iter()
+            .all(|(_, key, value)| value == &key.to_string()));
+        assert!(map.drain().all(|(_, key, value)| value == key.to_string()));
+    }
+
     #[test]
     fn remove() {
         for (key, expected_value, expected_order) in [
@@ -241,4 +2790,404 @@ mod tests {
             assert!(map.pop().is_none());
         }
     }
+
+    #[test]
+    fn min_order() {
+        let mut map = PriorityMap::new_min();
+        for (priority, key) in [(3, "c"), (1, "a"), (4, "d"), (2, "b")] {
+            map.insert(priority, key, ());
+        }
+
+        assert!(!map.insert_if_higher(5, "a", ()));
+        assert!(map.insert_if_higher(0, "d", ()));
+        let sorted: Vec<_> = map.iter_sorted().map(|(_, key, _)| *key).collect();
+        assert_eq!(sorted, ["d", "a", "b", "c"]);
+
+        assert_eq!(map.pop_full(), Some((0, "d", ())));
+        assert_eq!(
+            map.clone().into_sorted_vec(),
+            [(1, "a", ()), (2, "b", ()), (3, "c", ())]
+        );
+        assert_eq!(map.pop(), Some(()));
+        assert_eq!(map.peek_full(), Some((&2, &"b", &())));
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Job {
+        urgency: u8,
+        submitted: u32,
+    }
+
+    #[test]
+    fn with_order() {
+        let mut map = PriorityMap::with_order(|a: &Job, b: &Job| a.urgency > b.urgency);
+        map.insert(
+            Job {
+                urgency: 1,
+                submitted: 9,
+            },
+            "a",
+            (),
+        );
+        map.insert(
+            Job {
+                urgency: 3,
+                submitted: 1,
+            },
+            "b",
+            (),
+        );
+        map.insert(
+            Job {
+                urgency: 2,
+                submitted: 5,
+            },
+            "c",
+            (),
+        );
+
+        assert!(map
+            .reprioritize(
+                &"a",
+                Job {
+                    urgency: 4,
+                    submitted: 0
+                }
+            )
+            .is_some());
+        let keys: Vec<_> = map.iter_sorted().map(|(_, key, _)| *key).collect();
+        assert_eq!(keys, ["a", "b", "c"]);
+        assert_eq!(map.pop_full().map(|(_, key, _)| key), Some("a"));
+        assert_eq!(map.pop_full().map(|(_, key, _)| key), Some("b"));
+
+        let mut map = PriorityMap::with_order(crate::Min);
+        map.extend([(2, "b", ()), (1, "a", ())]);
+        assert_eq!(map.peek_full(), Some((&1, &"a", &())));
+    }
+
+    #[test]
+    fn min_priority_map() {
+        let mut map: MinPriorityMap<_, _, _> = MinPriorityMap::default();
+        map.insert(3, "c", ());
+        map.insert(1, "a", ());
+        map.insert(2, "b", ());
+        assert_eq!(map.peek_full(), Some((&1, &"a", &())));
+
+        map.increase_priority(&"a", 5);
+        let priorities: Vec<_> = map
+            .drain_sorted()
+            .map(|(priority, _, _)| priority)
+            .collect();
+        assert_eq!(priorities, [2, 3, 6]);
+    }
+
+    #[test]
+    fn then_by() {
+        let mut map = PriorityMap::with_order(crate::ThenBy(Max, Min));
+        map.insert((1, 0), "a", ());
+        map.insert((2, 3), "b", ());
+        map.insert((2, 1), "c", ());
+        map.insert((2, 2), "d", ());
+        map.insert((1, 4), "e", ());
+
+        let keys: Vec<_> = map.drain_sorted().map(|(_, key, _)| key).collect();
+        assert_eq!(keys, ["c", "d", "b", "a", "e"]);
+    }
+
+    #[test]
+    fn stable() {
+        let mut map = PriorityMap::new();
+        for (priority, key) in [(1, "a"), (2, "b"), (1, "c"), (2, "d"), (1, "e"), (2, "f")] {
+            map.insert(priority, key, ());
+        }
+        map.set_stable(true);
+        assert!(map.is_stable());
+
+        map.insert(2, "b", ());
+        map.insert(0, "g", ());
+        map.reprioritize(&"g", 1);
+        assert_eq!(
+            map.iter_sorted()
+                .map(|(_, key, _)| *key)
+                .collect::<Vec<_>>(),
+            ["b", "d", "f", "a", "c", "e", "g"]
+        );
+
+        let mut swapped = map.clone();
+        assert!(swapped.swap_priorities(&"a", &"d"));
+        assert_eq!(
+            swapped
+                .into_sorted_vec()
+                .iter()
+                .map(|(_, key, _)| *key)
+                .collect::<Vec<_>>(),
+            ["a", "b", "f", "c", "d", "e", "g"]
+        );
+
+        let mut top = map.clone();
+        top.truncate_top(2);
+        assert_eq!(top.into_sorted_vec(), [(2, "b", ()), (2, "d", ())]);
+
+        let keys: Vec<_> = std::iter::from_fn(|| map.pop_full().map(|(_, key, _)| key)).collect();
+        assert_eq!(keys, ["b", "d", "f", "a", "c", "e", "g"]);
+    }
+
+    #[test]
+    fn unordered_values() {
+        struct Payload(f64);
+
+        let mut map = PriorityMap::new();
+        map.insert(1, "a", Payload(0.5));
+        map.insert(2, "b", Payload(f64::NAN));
+        assert!(map.pop().is_some_and(|payload| payload.0.is_nan()));
+        assert_eq!(map.pop().map(|payload| payload.0), Some(0.5));
+    }
+
+    #[test]
+    fn non_clone_priorities() {
+        #[derive(PartialEq, PartialOrd)]
+        struct Deadline(Box<u32>);
+
+        let mut map = PriorityMap::new();
+        map.insert(Deadline(Box::new(1)), "a", ());
+        map.insert(Deadline(Box::new(3)), "b", ());
+        assert!(map.reprioritize(&"a", Deadline(Box::new(5))).is_some());
+        assert_eq!(map.pop_full().map(|(_, key, _)| key), Some("a"));
+        assert_eq!(map.pop_full().map(|(_, key, _)| key), Some("b"));
+    }
+
+    #[test]
+    fn non_clone_keys() {
+        #[derive(Debug, PartialEq, Eq, Hash)]
+        struct Key(u32);
+
+        let mut map = PriorityMap::new();
+        for i in 0..100 {
+            map.insert(i, Key(i), ());
+        }
+        for i in 0..100 {
+            map.insert(100 - i, Key(i), ());
+            map.reprioritize(&Key(i), i * 7 % 100);
+        }
+        assert_eq!(map.remove_full(&Key(99)), Some((93, ())));
+        assert_eq!(map.pop_full(), Some((99, Key(57), ())));
+        assert_eq!(map.len(), 98);
+    }
+
+    #[test]
+    fn from_iter_duplicates() {
+        let map: PriorityMap<_, _, _> = [(1, "a", 1), (5, "b", 2), (3, "a", 3), (2, "c", 4)]
+            .into_iter()
+            .collect();
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.get_full(&"a"), Some((&3, &"a", &3)));
+        assert_eq!(
+            map.into_sorted_vec(),
+            [(5, "b", 2), (3, "a", 3), (2, "c", 4)]
+        );
+
+        let mut map = PriorityMap::new();
+        map.insert(4, "d", 0);
+        map.extend((0..10).map(|i| (i, "e", i)));
+        map.extend([(8, "f", 0), (3, "d", 1)]);
+        assert_eq!(
+            map.into_sorted_vec(),
+            [(9, "e", 9), (8, "f", 0), (3, "d", 1)]
+        );
+    }
+
+    #[test]
+    fn batch_insert() {
+        let mut map = PriorityMap::new();
+        map.insert(5, "a", 0);
+
+        let mut batch = map.batch_insert();
+        batch.insert(1, "b", 1);
+        batch.insert(7, "c", 2);
+        batch.extend([(3, "d", 3), (0, "a", 4)]);
+        assert_eq!(batch.len(), 4);
+        batch.commit();
+
+        assert_eq!(
+            map.into_sorted_vec(),
+            [(7, "c", 2), (3, "d", 3), (1, "b", 1), (0, "a", 4)]
+        );
+    }
+
+    #[test]
+    fn compact() {
+        let mut map: PriorityMap<_, _, _> = (0..100).map(|i| (i, i, ())).collect();
+        map.retain(|priority, _, _| priority % 10 == 0);
+        map.compact();
+        assert!(map.capacity() < 100);
+        assert_eq!(map.get_priority(&50), Some(&50));
+        let priorities: Vec<_> = map
+            .drain_sorted()
+            .map(|(priority, _, _)| priority)
+            .collect();
+        assert_eq!(priorities, [90, 80, 70, 60, 50, 40, 30, 20, 10, 0]);
+    }
+
+    #[test]
+    fn panicking_order_drops_values_once() {
+        use std::cell::Cell;
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+        use std::rc::Rc;
+
+        let calls = Cell::new(0);
+        let order = |a: &u32, b: &u32| {
+            calls.set(calls.get() + 1);
+            assert!(calls.get() < 20, "comparison failed");
+            a > b
+        };
+        let value = Rc::new(());
+        let mut map = PriorityMap::with_order(order);
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            for i in 0..100 {
+                map.insert(i, i, Rc::clone(&value));
+            }
+        }));
+        assert!(result.is_err());
+        assert_eq!(Rc::strong_count(&value), map.len() + 1);
+        drop(map);
+        assert_eq!(Rc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn arity() {
+        fn check<const D: usize>(
+            map: PriorityMap<u32, u32, u32, RandomState, Max, DAryHeap<u32, D>>,
+        ) {
+            let mut map = map;
+            for i in 0..100 {
+                map.insert(i * 37 % 100, i, i);
+            }
+            map.remove(&7);
+            map.reprioritize(&8, 1000);
+            let sorted: Vec<_> = map
+                .iter_sorted()
+                .map(|(priority, _, _)| *priority)
+                .collect();
+            assert_eq!(sorted[0], 1000);
+            assert!(sorted.windows(2).all(|pair| pair[0] >= pair[1]));
+            let popped: Vec<_> = map
+                .drain_sorted()
+                .map(|(priority, _, _)| priority)
+                .collect();
+            assert_eq!(popped, sorted);
+        }
+
+        check(PriorityMap::new());
+        check(PriorityMap::new().into_arity::<3>());
+        check(PriorityMap::new().into_arity::<4>());
+        check(PriorityMap::new().into_arity::<8>());
+
+        let map: PriorityMap<_, _, _> = (0..20).map(|i| (i, i, ())).collect();
+        let mut map = map.into_arity::<5>();
+        assert_eq!(map.pop_batch(3), [(19, 19, ()), (18, 18, ()), (17, 17, ())]);
+    }
+
+    #[test]
+    fn sifting_does_not_move_values() {
+        let mut map = PriorityMap::with_capacity(100);
+        for i in 0..100 {
+            map.insert(i, i, [i; 64]);
+        }
+        let value: *const _ = map.get(&50).unwrap();
+        for i in 0..100 {
+            map.reprioritize(&i, 100 - i);
+        }
+        map.pop();
+        assert!(std::ptr::eq(map.get(&50).unwrap(), value));
+    }
+
+    #[test]
+    fn boxed_storage() {
+        let mut map: BoxedPriorityMap<u32, u32, String> =
+            (0..10).map(|i| (i, i, i.to_string())).collect();
+        assert_eq!(map.insert(20, 3, "three".into()), Some((3, "3".into())));
+        map.get_mut(&4).unwrap().push('!');
+        assert_eq!(map.get(&4).map(String::as_str), Some("4!"));
+        assert_eq!(map.pop(), Some("three".into()));
+        assert_eq!(map.remove(&9), Some("9".into()));
+        assert_eq!(map.iter().count(), 8);
+
+        let map = map.into_storage::<String>();
+        let sorted: Vec<_> = map
+            .into_sorted_vec()
+            .into_iter()
+            .map(|(_, key, _)| key)
+            .collect();
+        assert_eq!(sorted, [8, 7, 6, 5, 4, 2, 1, 0]);
+
+        let mut map: BoxedPriorityMap<_, _, _> = BoxedPriorityMap::default();
+        map.insert(1, 1, vec![1]);
+        map.insert(2, 2, vec![2]);
+        let mut drained: Vec<_> = map.drain().collect();
+        drained.sort();
+        assert_eq!(drained, [(1, 1, vec![1]), (2, 2, vec![2])]);
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn starvation_diagnostics() {
+        let mut map: TimestampedPriorityMap<_, _, _> = TimestampedPriorityMap::default();
+        map.insert(1, "a", ());
+        map.insert(3, "b", ());
+        map.insert(2, "c", ());
+        let inserted = map.inserted_at("a").unwrap();
+        assert_eq!(map.insert(5, "a", ()), Some((1, ())));
+        assert_eq!(map.inserted_at("a"), Some(inserted));
+        assert_eq!(map.longest_waiting(), Some((&5, &"a", &())));
+
+        let hour = Duration::from_secs(3600);
+        let now = Instant::now();
+        assert_eq!(map.starving(now, hour).count(), 0);
+        let mut starving: Vec<_> = map.starving(now + hour, hour).map(|(_, k, _)| *k).collect();
+        starving.sort();
+        assert_eq!(starving, ["a", "b", "c"]);
+
+        assert_eq!(map.pop(), Some(()));
+        assert_eq!(map.longest_waiting(), Some((&3, &"b", &())));
+    }
+
+    #[test]
+    fn append_keeps_timestamps() {
+        for len in [1, 100] {
+            let mut map: TimestampedPriorityMap<_, _, _> = TimestampedPriorityMap::default();
+            let mut other: TimestampedPriorityMap<_, _, _> = TimestampedPriorityMap::default();
+            other.extend((0..len).map(|i| (i, i, "other")));
+            map.insert(0, 0, "map");
+            map.insert(0, len, "map");
+            let inserted: Vec<_> = (0..=len)
+                .map(|i| map.inserted_at(&i).or(other.inserted_at(&i)))
+                .collect();
+
+            map.append(&mut other);
+            assert_eq!(map.get(&0), Some(&"other"));
+            for (i, inserted) in (0..=len).zip(inserted) {
+                assert_eq!(map.inserted_at(&i), inserted);
+            }
+        }
+    }
+
+    #[test]
+    fn merge_with_keeps_timestamps() {
+        for len in [1, 100] {
+            let mut map: TimestampedPriorityMap<_, _, _> = TimestampedPriorityMap::default();
+            let mut other: TimestampedPriorityMap<_, _, _> = TimestampedPriorityMap::default();
+            other.extend((0..len).map(|i| (i, i, 1)));
+            map.insert(0, 0, 1);
+            map.insert(0, len, 1);
+            let inserted: Vec<_> = (0..=len)
+                .map(|i| map.inserted_at(&i).or(other.inserted_at(&i)))
+                .collect();
+
+            map.merge_with(other, |p, v, q, w| (p + q, v + w));
+            assert_eq!(map.get(&0), Some(&2));
+            for (i, inserted) in (0..=len).zip(inserted) {
+                assert_eq!(map.inserted_at(&i), inserted);
+            }
+        }
+    }
 }
@@ -0,0 +1,83 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+use super::PriorityMap;
+use crate::backend::{DAryHeap, HeapBackend};
+use crate::order::{Max, PriorityOrder};
+use crate::storage::Storage;
+
+/// Buffers inserts into a [`PriorityMap`] without maintaining the heap.
+///
+/// The heap is rebuilt once in `O(n)` when the guard is committed or dropped, which is
+/// cheaper than sifting every insert when the batch is large compared to the map.
+pub struct BatchInsert<'a, P, K, V, S = RandomState, O = Max, B = DAryHeap<P>, W = V>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+    W: Storage<V>,
+    B: HeapBackend<P>,
+{
+    pub(super) map: &'a mut PriorityMap<P, K, V, S, O, B, W>,
+    pub(super) dirty: bool,
+}
+
+impl<P, K, V, S, O, B, W> BatchInsert<'_, P, K, V, S, O, B, W>
+where
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+{
+    /// Inserts an entry, replacing the priority and value of an existing entry with the
+    /// same key.
+    pub fn insert(&mut self, priority: P, key: K, value: V) {
+        self.dirty = true;
+        self.map.push_unordered(priority, key, value);
+    }
+
+    /// Returns the number of entries in the map, including the ones inserted so far.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Restores the heap property. Equivalent to dropping the guard.
+    pub fn commit(self) {}
+}
+
+impl<P, K, V, S, O, B, W> Extend<(P, K, V)> for BatchInsert<'_, P, K, V, S, O, B, W>
+where
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+{
+    fn extend<I: IntoIterator<Item = (P, K, V)>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        self.map.reserve(iter.size_hint().0);
+        for (priority, key, value) in iter {
+            self.insert(priority, key, value);
+        }
+    }
+}
+
+impl<P, K, V, S, O, B, W> Drop for BatchInsert<'_, P, K, V, S, O, B, W>
+where
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+{
+    fn drop(&mut self) {
+        if self.dirty {
+            self.map.rebuild();
+        }
+    }
+}
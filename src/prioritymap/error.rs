@@ -0,0 +1,20 @@
+use std::fmt;
+
+/// The error returned by [`try_insert`](super::PriorityMap::try_insert) when the key
+/// is already present.
+///
+/// Contains the entry that was rejected.
+#[derive(Debug, PartialEq, Eq)]
+pub struct OccupiedError<P, K, V> {
+    pub priority: P,
+    pub key: K,
+    pub value: V,
+}
+
+impl<P, K, V> fmt::Display for OccupiedError<P, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("key already exists in the priority map")
+    }
+}
+
+impl<P: fmt::Debug, K: fmt::Debug, V: fmt::Debug> std::error::Error for OccupiedError<P, K, V> {}
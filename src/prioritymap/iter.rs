@@ -0,0 +1,553 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::iter::{Enumerate, Zip};
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
+
+use super::{PriorityMap, Slot};
+use crate::backend::{DAryHeap, HeapBackend, Ranking};
+use crate::order::{Max, PriorityOrder};
+use crate::storage::{Storage, Timestamped};
+
+pub struct Iter<'a, P, K, V, B = DAryHeap<P>, W = V> {
+    pub(super) heap: &'a B,
+    pub(super) inner: Enumerate<std::slice::Iter<'a, Slot<K, W>>>,
+    pub(super) value: PhantomData<&'a (P, V)>,
+}
+
+impl<'a, P, K, V, B: HeapBackend<P>, W: Storage<V>> Iterator for Iter<'a, P, K, V, B, W> {
+    type Item = (&'a P, &'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (i, slot) = self.inner.next()?;
+        Some((self.heap.priority(i), &slot.key, slot.value.value()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<P, K, V, B: HeapBackend<P>, W: Storage<V>> ExactSizeIterator for Iter<'_, P, K, V, B, W> {}
+
+impl<P, K, V, B, W> Clone for Iter<'_, P, K, V, B, W> {
+    fn clone(&self) -> Self {
+        Self {
+            heap: self.heap,
+            inner: self.inner.clone(),
+            value: PhantomData,
+        }
+    }
+}
+
+pub struct IterMut<'a, P, K, V, B = DAryHeap<P>, W = V> {
+    pub(super) heap: &'a B,
+    pub(super) inner: Enumerate<std::slice::IterMut<'a, Slot<K, W>>>,
+    pub(super) value: PhantomData<&'a mut (P, V)>,
+}
+
+impl<'a, P, K, V, B: HeapBackend<P>, W: Storage<V>> Iterator for IterMut<'a, P, K, V, B, W> {
+    type Item = (&'a P, &'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (i, slot) = self.inner.next()?;
+        Some((self.heap.priority(i), &slot.key, slot.value.value_mut()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<P, K, V, B: HeapBackend<P>, W: Storage<V>> ExactSizeIterator for IterMut<'_, P, K, V, B, W> {}
+
+pub struct IntoIter<P, K, V, B: HeapBackend<P> = DAryHeap<P>, W = V> {
+    pub(super) inner: Zip<B::IntoNodes, std::vec::IntoIter<Slot<K, W>>>,
+    pub(super) value: PhantomData<V>,
+}
+
+impl<P, K, V, B: HeapBackend<P>, W: Storage<V>> Iterator for IntoIter<P, K, V, B, W> {
+    type Item = (P, K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let ((priority, _), slot) = self.inner.next()?;
+        Some((priority, slot.key, slot.value.into_value()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<P, K, V, B: HeapBackend<P>, W: Storage<V>> ExactSizeIterator for IntoIter<P, K, V, B, W> {}
+
+pub struct Drain<'a, P, K, V, B: HeapBackend<P> + 'a = DAryHeap<P>, W = V> {
+    pub(super) inner: Zip<B::Drain<'a>, std::vec::Drain<'a, Slot<K, W>>>,
+    pub(super) value: PhantomData<V>,
+}
+
+impl<P, K, V, B: HeapBackend<P>, W: Storage<V>> Iterator for Drain<'_, P, K, V, B, W> {
+    type Item = (P, K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let ((priority, _), slot) = self.inner.next()?;
+        Some((priority, slot.key, slot.value.into_value()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<P, K, V, B: HeapBackend<P>, W: Storage<V>> ExactSizeIterator for Drain<'_, P, K, V, B, W> {}
+
+/// Yields entries by descending priority.
+///
+/// Keeps a frontier of slots whose parents in the heap have already been yielded,
+/// so each step costs `O(log k)` where `k` is the size of the frontier.
+pub struct IterSorted<'a, P, K, V, O = Max, B = DAryHeap<P>, W = V> {
+    heap: &'a B,
+    slots: &'a [Slot<K, W>],
+    ranking: &'a Ranking<O>,
+    frontier: Vec<usize>,
+    value: PhantomData<&'a (P, V)>,
+}
+
+impl<'a, P, K, V, O, B, W> IterSorted<'a, P, K, V, O, B, W>
+where
+    O: PriorityOrder<P>,
+    B: HeapBackend<P>,
+{
+    pub(super) fn new(heap: &'a B, slots: &'a [Slot<K, W>], ranking: &'a Ranking<O>) -> Self {
+        Self {
+            heap,
+            slots,
+            ranking,
+            frontier: heap.top().into_iter().collect(),
+            value: PhantomData,
+        }
+    }
+
+    fn higher(&self, a: usize, b: usize) -> bool {
+        let (a, b) = (self.frontier[a], self.frontier[b]);
+        self.ranking.higher(
+            (self.heap.priority(a), self.heap.sequence(a)),
+            (self.heap.priority(b), self.heap.sequence(b)),
+        )
+    }
+
+    fn push(&mut self, slot: usize) {
+        self.frontier.push(slot);
+        let mut i = self.frontier.len() - 1;
+        while i > 0 {
+            let parent = (i - 1) / 2;
+            if !self.higher(i, parent) {
+                break;
+            }
+            self.frontier.swap(i, parent);
+            i = parent;
+        }
+    }
+
+    fn pop(&mut self) -> Option<usize> {
+        if self.frontier.is_empty() {
+            return None;
+        }
+        let top = self.frontier.swap_remove(0);
+        let mut i = 0;
+        loop {
+            let left = 2 * i + 1;
+            if left >= self.frontier.len() {
+                break;
+            }
+            let right = left + 1;
+            let child = if right < self.frontier.len() && self.higher(right, left) {
+                right
+            } else {
+                left
+            };
+            if !self.higher(child, i) {
+                break;
+            }
+            self.frontier.swap(child, i);
+            i = child;
+        }
+        Some(top)
+    }
+}
+
+impl<'a, P, K, V, O, B, W> Iterator for IterSorted<'a, P, K, V, O, B, W>
+where
+    O: PriorityOrder<P>,
+    W: Storage<V>,
+    B: HeapBackend<P>,
+{
+    type Item = (&'a P, &'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let slot = self.pop()?;
+        let heap = self.heap;
+        heap.children(slot, |child| self.push(child));
+        let entry = &self.slots[slot];
+        Some((heap.priority(slot), &entry.key, entry.value.value()))
+    }
+}
+
+/// Pops entries by descending priority as it is advanced.
+///
+/// Entries that have not been yielded are removed when the iterator is dropped.
+pub struct DrainSorted<'a, P, K, V, S = RandomState, O = Max, B = DAryHeap<P>, W = V>
+where
+    K: Hash,
+    B: HeapBackend<P>,
+{
+    pub(super) map: &'a mut PriorityMap<P, K, V, S, O, B, W>,
+}
+
+impl<P, K, V, S, O, B, W> Iterator for DrainSorted<'_, P, K, V, S, O, B, W>
+where
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+{
+    type Item = (P, K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.map.pop_full()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.map.heap.len();
+        (len, Some(len))
+    }
+}
+
+impl<P, K, V, S, O, B, W> ExactSizeIterator for DrainSorted<'_, P, K, V, S, O, B, W>
+where
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+{
+}
+
+impl<P, K, V, S, O, B, W> Drop for DrainSorted<'_, P, K, V, S, O, B, W>
+where
+    K: Hash,
+    B: HeapBackend<P>,
+{
+    fn drop(&mut self) {
+        self.map.heap.clear();
+        self.map.slots.clear();
+        self.map.index.clear();
+    }
+}
+
+pub struct Keys<'a, P, K, V, B, W> {
+    pub(super) inner: Iter<'a, P, K, V, B, W>,
+}
+
+impl<'a, P, K, V, B: HeapBackend<P>, W: Storage<V>> Iterator for Keys<'a, P, K, V, B, W> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (_, key, _) = self.inner.next()?;
+        Some(key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<P, K, V, B: HeapBackend<P>, W: Storage<V>> ExactSizeIterator for Keys<'_, P, K, V, B, W> {}
+
+impl<P, K, V, B, W> Clone for Keys<'_, P, K, V, B, W> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+pub struct Values<'a, P, K, V, B, W> {
+    pub(super) inner: Iter<'a, P, K, V, B, W>,
+}
+
+impl<'a, P, K, V, B: HeapBackend<P>, W: Storage<V>> Iterator for Values<'a, P, K, V, B, W> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (_, _, value) = self.inner.next()?;
+        Some(value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<P, K, V, B: HeapBackend<P>, W: Storage<V>> ExactSizeIterator for Values<'_, P, K, V, B, W> {}
+
+impl<P, K, V, B, W> Clone for Values<'_, P, K, V, B, W> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+pub struct Priorities<'a, P, K, V, B, W> {
+    pub(super) inner: Iter<'a, P, K, V, B, W>,
+}
+
+impl<'a, P, K, V, B: HeapBackend<P>, W: Storage<V>> Iterator for Priorities<'a, P, K, V, B, W> {
+    type Item = &'a P;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (priority, _, _) = self.inner.next()?;
+        Some(priority)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<P, K, V, B: HeapBackend<P>, W: Storage<V>> ExactSizeIterator
+    for Priorities<'_, P, K, V, B, W>
+{
+}
+
+impl<P, K, V, B, W> Clone for Priorities<'_, P, K, V, B, W> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+pub struct IntoKeys<P, K, V, B: HeapBackend<P>, W> {
+    pub(super) inner: IntoIter<P, K, V, B, W>,
+}
+
+impl<P, K, V, B: HeapBackend<P>, W: Storage<V>> Iterator for IntoKeys<P, K, V, B, W> {
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (_, key, _) = self.inner.next()?;
+        Some(key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<P, K, V, B: HeapBackend<P>, W: Storage<V>> ExactSizeIterator for IntoKeys<P, K, V, B, W> {}
+
+pub struct IntoValues<P, K, V, B: HeapBackend<P>, W> {
+    pub(super) inner: IntoIter<P, K, V, B, W>,
+}
+
+impl<P, K, V, B: HeapBackend<P>, W: Storage<V>> Iterator for IntoValues<P, K, V, B, W> {
+    type Item = V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (_, _, value) = self.inner.next()?;
+        Some(value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<P, K, V, B: HeapBackend<P>, W: Storage<V>> ExactSizeIterator for IntoValues<P, K, V, B, W> {}
+
+/// Removes and yields the entries for which the predicate returns `true`, in arbitrary order.
+///
+/// The heap is rebuilt once when the iterator is dropped.
+pub struct ExtractIf<'a, P, K, V, F, S = RandomState, O = Max, B = DAryHeap<P>, W = V>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    F: FnMut(&P, &K, &mut V) -> bool,
+{
+    pub(super) map: &'a mut PriorityMap<P, K, V, S, O, B, W>,
+    pub(super) slot: usize,
+    pub(super) removed: bool,
+    pub(super) pred: F,
+}
+
+impl<P, K, V, F, S, O, B, W> Iterator for ExtractIf<'_, P, K, V, F, S, O, B, W>
+where
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+    F: FnMut(&P, &K, &mut V) -> bool,
+{
+    type Item = (P, K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.slot < self.map.slots.len() {
+            let entry = &mut self.map.slots[self.slot];
+            let priority = self.map.heap.priority(self.slot);
+            if (self.pred)(priority, &entry.key, entry.value.value_mut()) {
+                // Defer restoring the heap property until drop, only keep the index consistent.
+                // The last entry moves into this slot, so it is visited next.
+                let entry = self.map.remove_slot_unordered(self.slot);
+                self.removed = true;
+                return Some((entry.priority, entry.key, entry.value.into_value()));
+            }
+            self.slot += 1;
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.map.heap.len() - self.slot))
+    }
+}
+
+impl<P, K, V, F, S, O, B, W> Drop for ExtractIf<'_, P, K, V, F, S, O, B, W>
+where
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+    F: FnMut(&P, &K, &mut V) -> bool,
+{
+    fn drop(&mut self) {
+        if self.removed {
+            self.map.rebuild();
+        }
+    }
+}
+
+/// Pops entries by descending priority as long as the predicate holds for the top entry.
+pub struct PopWhile<'a, P, K, V, F, S = RandomState, O = Max, B = DAryHeap<P>, W = V>
+where
+    K: Hash,
+{
+    pub(super) map: &'a mut PriorityMap<P, K, V, S, O, B, W>,
+    pub(super) pred: F,
+}
+
+impl<P, K, V, F, S, O, B, W> Iterator for PopWhile<'_, P, K, V, F, S, O, B, W>
+where
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+    F: FnMut(&P, &K, &V) -> bool,
+{
+    type Item = (P, K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.map.pop_if(&mut self.pred)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.map.heap.len()))
+    }
+}
+
+/// Pops up to `n` entries by descending priority as it is advanced.
+///
+/// Entries among the `n` highest that have not been yielded are removed when the
+/// iterator is dropped.
+pub struct DrainTop<'a, P, K, V, S = RandomState, O = Max, B = DAryHeap<P>, W = V>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+    W: Storage<V>,
+    B: HeapBackend<P>,
+{
+    pub(super) map: &'a mut PriorityMap<P, K, V, S, O, B, W>,
+    pub(super) remaining: usize,
+}
+
+impl<P, K, V, S, O, B, W> Iterator for DrainTop<'_, P, K, V, S, O, B, W>
+where
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+{
+    type Item = (P, K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        self.map.pop_full()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.remaining.min(self.map.heap.len());
+        (len, Some(len))
+    }
+}
+
+impl<P, K, V, S, O, B, W> ExactSizeIterator for DrainTop<'_, P, K, V, S, O, B, W>
+where
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+{
+}
+
+impl<P, K, V, S, O, B, W> Drop for DrainTop<'_, P, K, V, S, O, B, W>
+where
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+{
+    fn drop(&mut self) {
+        self.for_each(drop);
+    }
+}
+
+/// Yields the entries that have waited for at least a threshold, see
+/// [`PriorityMap::starving`].
+pub struct Starving<'a, P, K, V, B = DAryHeap<P>> {
+    pub(super) heap: &'a B,
+    pub(super) inner: Enumerate<std::slice::Iter<'a, Slot<K, Timestamped<V>>>>,
+    pub(super) now: Instant,
+    pub(super) threshold: Duration,
+    pub(super) priority: PhantomData<&'a P>,
+}
+
+impl<'a, P, K, V, B: HeapBackend<P>> Iterator for Starving<'a, P, K, V, B> {
+    type Item = (&'a P, &'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (now, threshold) = (self.now, self.threshold);
+        let (i, slot) = self
+            .inner
+            .find(|(_, slot)| now.saturating_duration_since(slot.value.inserted()) >= threshold)?;
+        Some((self.heap.priority(i), &slot.key, slot.value.value()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.inner.size_hint().1)
+    }
+}
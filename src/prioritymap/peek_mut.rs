@@ -0,0 +1,97 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::ops::{Deref, DerefMut};
+
+use super::PriorityMap;
+use crate::backend::{DAryHeap, HeapBackend};
+use crate::order::{Max, PriorityOrder};
+use crate::storage::Storage;
+
+/// Mutable access to the top entry of a [`PriorityMap`].
+///
+/// Dereferences to the value. If the priority was accessed mutably, the entry is
+/// moved to its correct position when the guard is dropped.
+pub struct PeekMut<'a, P, K, V, S = RandomState, O = Max, B = DAryHeap<P>, W = V>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+    W: Storage<V>,
+    B: HeapBackend<P>,
+{
+    pub(super) map: &'a mut PriorityMap<P, K, V, S, O, B, W>,
+    /// The slot of the top entry.
+    pub(super) slot: usize,
+    pub(super) sift: bool,
+}
+
+impl<P, K, V, S, O, B, W> PeekMut<'_, P, K, V, S, O, B, W>
+where
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+{
+    pub fn priority(&self) -> &P {
+        self.map.heap.priority(self.slot)
+    }
+
+    pub fn priority_mut(&mut self) -> &mut P {
+        self.sift = true;
+        self.map.heap.priority_mut(self.slot)
+    }
+
+    pub fn key(&self) -> &K {
+        &self.map.slots[self.slot].key
+    }
+
+    /// Removes the peeked entry from the map and returns it.
+    pub fn pop(mut this: Self) -> (P, K, V) {
+        this.sift = false;
+        this.map.pop_full().expect("peeked entry must exist")
+    }
+}
+
+impl<P, K, V, S, O, B, W> Deref for PeekMut<'_, P, K, V, S, O, B, W>
+where
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+{
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        self.map.slots[self.slot].value.value()
+    }
+}
+
+impl<P, K, V, S, O, B, W> DerefMut for PeekMut<'_, P, K, V, S, O, B, W>
+where
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+{
+    fn deref_mut(&mut self) -> &mut V {
+        self.map.slots[self.slot].value.value_mut()
+    }
+}
+
+impl<P, K, V, S, O, B, W> Drop for PeekMut<'_, P, K, V, S, O, B, W>
+where
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+{
+    fn drop(&mut self) {
+        if self.sift {
+            self.map.heap.lower(self.slot, &self.map.ranking);
+        }
+    }
+}
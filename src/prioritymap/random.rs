@@ -0,0 +1,139 @@
+use std::hash::{BuildHasher, Hash};
+
+use rand::seq::index;
+use rand::Rng;
+
+use super::PriorityMap;
+use crate::backend::HeapBackend;
+use crate::order::PriorityOrder;
+use crate::storage::Storage;
+
+impl<P, K, V, S, O, B, W> PriorityMap<P, K, V, S, O, B, W>
+where
+    W: Storage<V>,
+    B: HeapBackend<P>,
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+{
+    /// Returns a uniformly random entry without removing it, or `None` if the map is empty.
+    pub fn sample<R>(&self, rng: &mut R) -> Option<(&P, &K, &V)>
+    where
+        R: Rng + ?Sized,
+    {
+        if self.slots.is_empty() {
+            return None;
+        }
+        Some(self.slot_entry(rng.gen_range(0..self.slots.len())))
+    }
+
+    /// Returns up to `n` distinct entries picked uniformly at random, in random order,
+    /// without removing them.
+    pub fn sample_n<R>(&self, rng: &mut R, n: usize) -> Vec<(&P, &K, &V)>
+    where
+        R: Rng + ?Sized,
+    {
+        let len = self.slots.len();
+        index::sample(rng, len, n.min(len))
+            .into_iter()
+            .map(|slot| self.slot_entry(slot))
+            .collect()
+    }
+
+    /// Removes a random entry, picked with probability proportional to its priority.
+    ///
+    /// Entries whose priority is negative, zero, infinite or NaN are never picked. Returns
+    /// `None` if no priority is positive and finite. Takes `O(n)` time, or `O(log n)` with a
+    /// [`Weighted`](crate::Weighted) backend.
+    pub fn pop_weighted<R>(&mut self, rng: &mut R) -> Option<(P, K, V)>
+    where
+        P: Copy + Into<f64>,
+        R: Rng + ?Sized,
+    {
+        let slot = self.heap.weighted_slot(|total| rng.gen_range(0.0..total))?;
+        let entry = self.remove_slot(slot);
+        Some((entry.priority, entry.key, entry.value.into_value()))
+    }
+
+    fn slot_entry(&self, slot: usize) -> (&P, &K, &V) {
+        let entry = &self.slots[slot];
+        (self.heap.priority(slot), &entry.key, entry.value.value())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::PriorityMap;
+
+    #[test]
+    fn sample() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut map = PriorityMap::new();
+        assert_eq!(map.sample(&mut rng), None);
+        assert!(map.sample_n(&mut rng, 3).is_empty());
+
+        map.extend((0..10).map(|i| (i, i, i * 10)));
+        let mut seen = [false; 10];
+        for _ in 0..200 {
+            let (&priority, &key, &value) = map.sample(&mut rng).unwrap();
+            assert_eq!((priority, value), (key, key * 10));
+            seen[key] = true;
+        }
+        assert!(seen.iter().all(|&seen| seen));
+
+        let mut keys: Vec<_> = map
+            .sample_n(&mut rng, 4)
+            .into_iter()
+            .map(|e| *e.1)
+            .collect();
+        keys.sort();
+        keys.dedup();
+        assert_eq!(keys.len(), 4);
+        assert_eq!(map.sample_n(&mut rng, 20).len(), 10);
+        assert_eq!(map.len(), 10);
+    }
+
+    #[test]
+    fn pop_weighted() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut map = PriorityMap::new();
+        assert_eq!(map.pop_weighted(&mut rng), None);
+        map.insert(0u32, "zero", ());
+        assert_eq!(map.pop_weighted(&mut rng), None);
+
+        let mut counts = [0; 2];
+        for _ in 0..1000 {
+            map.insert(1, "one", ());
+            map.insert(3, "three", ());
+            match map.pop_weighted(&mut rng) {
+                Some((1, "one", ())) => counts[0] += 1,
+                Some((3, "three", ())) => counts[1] += 1,
+                other => panic!("unexpected pick {other:?}"),
+            }
+        }
+        assert!((650..850).contains(&counts[1]), "{counts:?}");
+        assert_eq!(map.len(), 2);
+        assert!(map.contains_key("zero"));
+    }
+
+    #[test]
+    fn pop_weighted_overflow() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut map = PriorityMap::new();
+        map.insert(f64::INFINITY, "inf", ());
+        assert_eq!(map.pop_weighted(&mut rng), None);
+
+        map.insert(f64::MAX, "a", ());
+        map.insert(f64::MAX, "b", ());
+        let mut popped = vec![];
+        while let Some((_, key, ())) = map.pop_weighted(&mut rng) {
+            popped.push(key);
+        }
+        popped.sort();
+        assert_eq!(popped, ["a", "b"]);
+        assert_eq!(map.len(), 1);
+    }
+}
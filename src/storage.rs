@@ -0,0 +1,112 @@
+use std::time::Instant;
+
+/// How a [`PriorityMap`](crate::PriorityMap) stores its values of type `V`.
+///
+/// `V` itself stores values inline in the map's slots, which is the default. `Box<V>`
+/// stores every value in its own allocation, which keeps the slots small and dense: key
+/// lookups touch less memory, and removing an entry moves a pointer instead of the value.
+/// The public API takes and returns plain `V` either way.
+pub trait Storage<V> {
+    /// Wraps a value for storage in the map.
+    fn store(value: V) -> Self;
+
+    /// Returns the stored value.
+    fn value(&self) -> &V;
+
+    /// Returns the stored value mutably.
+    fn value_mut(&mut self) -> &mut V;
+
+    /// Unwraps the stored value.
+    fn into_value(self) -> V;
+
+    /// Replaces the stored value with the one `f` computes from it, keeping anything
+    /// stored alongside the value.
+    fn map_value(self, f: impl FnOnce(V) -> V) -> Self
+    where
+        Self: Sized,
+    {
+        Self::store(f(self.into_value()))
+    }
+}
+
+impl<V> Storage<V> for V {
+    fn store(value: V) -> Self {
+        value
+    }
+
+    fn value(&self) -> &V {
+        self
+    }
+
+    fn value_mut(&mut self) -> &mut V {
+        self
+    }
+
+    fn into_value(self) -> V {
+        self
+    }
+}
+
+impl<V> Storage<V> for Box<V> {
+    fn store(value: V) -> Self {
+        Box::new(value)
+    }
+
+    fn value(&self) -> &V {
+        self
+    }
+
+    fn value_mut(&mut self) -> &mut V {
+        self
+    }
+
+    fn into_value(self) -> V {
+        *self
+    }
+}
+
+/// Stores every value with the time it was inserted, for the starvation diagnostics of
+/// [`TimestampedPriorityMap`](crate::TimestampedPriorityMap).
+///
+/// Replacing the value or priority of an existing key keeps the time, since the entry
+/// keeps waiting.
+#[derive(Clone, Debug)]
+pub struct Timestamped<V> {
+    value: V,
+    inserted: Instant,
+}
+
+impl<V> Timestamped<V> {
+    /// Returns when the entry was inserted.
+    pub fn inserted(&self) -> Instant {
+        self.inserted
+    }
+}
+
+impl<V> Storage<V> for Timestamped<V> {
+    fn store(value: V) -> Self {
+        Self {
+            value,
+            inserted: Instant::now(),
+        }
+    }
+
+    fn value(&self) -> &V {
+        &self.value
+    }
+
+    fn value_mut(&mut self) -> &mut V {
+        &mut self.value
+    }
+
+    fn into_value(self) -> V {
+        self.value
+    }
+
+    fn map_value(self, f: impl FnOnce(V) -> V) -> Self {
+        Self {
+            value: f(self.value),
+            inserted: self.inserted,
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+/// An `f64` priority that is totally ordered, so `NaN` cannot corrupt the heap.
+///
+/// Compares with [`f64::total_cmp`]: positive `NaN` ranks above infinity, negative `NaN`
+/// below negative infinity, and `-0.0` below `0.0`.
+#[derive(Clone, Copy, Default)]
+pub struct TotalF64(pub f64);
+
+impl PartialEq for TotalF64 {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for TotalF64 {}
+
+impl PartialOrd for TotalF64 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TotalF64 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl Hash for TotalF64 {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
+}
+
+impl From<f64> for TotalF64 {
+    fn from(value: f64) -> Self {
+        Self(value)
+    }
+}
+
+impl From<TotalF64> for f64 {
+    fn from(value: TotalF64) -> Self {
+        value.0
+    }
+}
+
+impl fmt::Debug for TotalF64 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Display for TotalF64 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TotalF64;
+    use crate::PriorityMap;
+
+    #[test]
+    fn nan_priorities() {
+        let mut map = PriorityMap::new();
+        for (priority, key) in [
+            (1.0, "a"),
+            (f64::NAN, "b"),
+            (-2.5, "c"),
+            (f64::INFINITY, "d"),
+        ] {
+            map.insert(TotalF64(priority), key, ());
+        }
+        map.insert(TotalF64(-f64::NAN), "e", ());
+
+        let keys: Vec<_> = map.drain_sorted().map(|(_, key, _)| key).collect();
+        assert_eq!(keys, ["b", "d", "a", "c", "e"]);
+    }
+}
@@ -0,0 +1,285 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::time::{Duration, Instant};
+
+use crate::evict::{Discard, Evict};
+use crate::order::{Max, PriorityOrder};
+use crate::PriorityMap;
+
+/// A [`PriorityMap`] whose entries may expire.
+///
+/// Expired entries never pop: [`pop`](Self::pop) and [`peek`](Self::peek) skip them
+/// regardless of their priority, and [`purge_expired`](Self::purge_expired) removes them
+/// all in a single `O(n)` pass. Either way, they go to the [`Evict`] hook `E`.
+pub struct TtlPriorityMap<P, K, V, S = RandomState, O = Max, E = Discard>
+where
+    K: Hash,
+{
+    map: PriorityMap<P, K, Expiring<V>, S, O>,
+    /// No entry expires before this instant.
+    next_expiry: Option<Instant>,
+    evict: E,
+}
+
+struct Expiring<V> {
+    value: V,
+    expiry: Option<Instant>,
+}
+
+impl<V> Expiring<V> {
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expiry.is_some_and(|expiry| expiry <= now)
+    }
+}
+
+impl<P, K, V> TtlPriorityMap<P, K, V>
+where
+    P: PartialOrd,
+    K: Eq + Hash,
+{
+    pub fn new() -> Self {
+        Self::with_order(Max)
+    }
+}
+
+impl<P, K, V> Default for TtlPriorityMap<P, K, V>
+where
+    P: PartialOrd,
+    K: Eq + Hash,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P, K, V, O> TtlPriorityMap<P, K, V, RandomState, O>
+where
+    K: Eq + Hash,
+    O: PriorityOrder<P>,
+{
+    pub fn with_order(order: O) -> Self {
+        Self {
+            map: PriorityMap::with_order(order),
+            next_expiry: None,
+            evict: Discard,
+        }
+    }
+}
+
+impl<P, K, V, S, O, E> TtlPriorityMap<P, K, V, S, O, E>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+    O: PriorityOrder<P>,
+    E: Evict<P, K, V>,
+{
+    /// Replaces the hook that receives expired entries.
+    pub fn on_evict<F>(self, evict: F) -> TtlPriorityMap<P, K, V, S, O, F>
+    where
+        F: Evict<P, K, V>,
+    {
+        TtlPriorityMap {
+            map: self.map,
+            next_expiry: self.next_expiry,
+            evict,
+        }
+    }
+
+    /// Returns the number of entries, including expired ones that were not purged yet.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Inserts an entry that never expires, replacing the priority, value and expiry of an
+    /// existing entry with the same key.
+    ///
+    /// Returns the previous priority and value.
+    pub fn insert(&mut self, priority: P, key: K, value: V) -> Option<(P, V)> {
+        self.insert_expiring(priority, key, value, None)
+    }
+
+    /// Inserts an entry that expires `ttl` from now, replacing the priority, value and
+    /// expiry of an existing entry with the same key.
+    ///
+    /// Returns the previous priority and value.
+    pub fn insert_with_ttl(
+        &mut self,
+        priority: P,
+        key: K,
+        value: V,
+        ttl: Duration,
+    ) -> Option<(P, V)> {
+        self.insert_with_expiry(priority, key, value, Instant::now() + ttl)
+    }
+
+    /// Inserts an entry that expires at `expiry`, replacing the priority, value and expiry
+    /// of an existing entry with the same key.
+    ///
+    /// Returns the previous priority and value.
+    pub fn insert_with_expiry(
+        &mut self,
+        priority: P,
+        key: K,
+        value: V,
+        expiry: Instant,
+    ) -> Option<(P, V)> {
+        if self.next_expiry.is_none_or(|next| expiry < next) {
+            self.next_expiry = Some(expiry);
+        }
+        self.insert_expiring(priority, key, value, Some(expiry))
+    }
+
+    fn insert_expiring(
+        &mut self,
+        priority: P,
+        key: K,
+        value: V,
+        expiry: Option<Instant>,
+    ) -> Option<(P, V)> {
+        let (priority, old) = self.map.insert(priority, key, Expiring { value, expiry })?;
+        Some((priority, old.value))
+    }
+
+    /// Returns when the entry expires, or `None` if it never expires or does not exist.
+    pub fn expiry<Q>(&self, key: &Q) -> Option<Instant>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.map.get(key)?.expiry
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<(P, V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let (priority, entry) = self.map.remove_full(key)?;
+        Some((priority, entry.value))
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.map.contains_key(key)
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        Some(&self.map.get(key)?.value)
+    }
+
+    pub fn get_priority<Q>(&self, key: &Q) -> Option<&P>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.map.get_priority(key)
+    }
+
+    /// Returns the top entry that has not expired, evicting expired entries above it.
+    pub fn peek(&mut self) -> Option<(&P, &K, &V)> {
+        self.evict_expired_top(Instant::now());
+        let (priority, key, entry) = self.map.peek_full()?;
+        Some((priority, key, &entry.value))
+    }
+
+    /// Pops the top entry that has not expired, evicting expired entries above it.
+    pub fn pop(&mut self) -> Option<(P, K, V)> {
+        self.evict_expired_top(Instant::now());
+        let (priority, key, entry) = self.map.pop_full()?;
+        Some((priority, key, entry.value))
+    }
+
+    /// Removes all entries that expired at `now` and returns how many there were.
+    ///
+    /// Returns immediately if no entry can have expired yet.
+    pub fn purge_expired(&mut self, now: Instant) -> usize {
+        if self.next_expiry.is_none_or(|next| next > now) {
+            return 0;
+        }
+        let mut next_expiry = None;
+        let mut purged = 0;
+        let extracted = self.map.extract_if(|_, _, entry| {
+            if entry.is_expired(now) {
+                return true;
+            }
+            if let Some(expiry) = entry.expiry {
+                next_expiry = Some(next_expiry.map_or(expiry, |next: Instant| next.min(expiry)));
+            }
+            false
+        });
+        for (priority, key, entry) in extracted {
+            self.evict.evict(priority, key, entry.value);
+            purged += 1;
+        }
+        self.next_expiry = next_expiry;
+        purged
+    }
+
+    fn evict_expired_top(&mut self, now: Instant) {
+        while self
+            .map
+            .peek_full()
+            .is_some_and(|(_, _, entry)| entry.is_expired(now))
+        {
+            let (priority, key, entry) = self.map.pop_full().expect("top entry exists");
+            self.evict.evict(priority, key, entry.value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::TtlPriorityMap;
+
+    #[test]
+    fn skips_expired() {
+        let mut evicted = vec![];
+        let mut map = TtlPriorityMap::new().on_evict(|_, key, ()| evicted.push(key));
+        map.insert(1, "a", ());
+        map.insert_with_ttl(3, "b", (), Duration::ZERO);
+        map.insert_with_ttl(2, "c", (), Duration::from_secs(3600));
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.peek(), Some((&2, &"c", &())));
+        assert_eq!(map.len(), 2);
+        assert!(map.expiry("c").is_some());
+        assert_eq!(map.expiry("a"), None);
+        assert_eq!(map.pop(), Some((2, "c", ())));
+        assert_eq!(map.pop(), Some((1, "a", ())));
+        assert_eq!(map.pop(), None);
+        drop(map);
+        assert_eq!(evicted, ["b"]);
+    }
+
+    #[test]
+    fn purge_expired() {
+        let start = Instant::now();
+        let mut map = TtlPriorityMap::new();
+        for i in 0..10u64 {
+            map.insert_with_expiry(i, i, (), start + Duration::from_secs(i));
+        }
+        map.insert(100, 100, ());
+        assert_eq!(map.purge_expired(start), 1);
+        assert_eq!(map.purge_expired(start), 0);
+        assert_eq!(map.purge_expired(start + Duration::from_secs(4)), 4);
+        assert_eq!(map.len(), 6);
+        // Replacing an entry does not expire it early.
+        map.insert(5, 5, ());
+        assert_eq!(map.purge_expired(start + Duration::from_secs(20)), 4);
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.purge_expired(start + Duration::from_secs(30)), 0);
+    }
+}